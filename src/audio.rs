@@ -1,76 +1,511 @@
 //! Everything you need to play audio in repl.it.
 
-use std::sync::atomic::{AtomicU64, Ordering};
-use std::{error, fmt, fs};
-use std::io::Write;
+use std::any::Any;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::{env, error, fmt, io, process};
+use std::ops::{Deref, DerefMut};
+use std::path::Path;
+use std::str::FromStr;
 use std::time::{Instant, Duration};
 
 use lazy_static::lazy_static;
 use json::{self, object};
-use chrono::NaiveDateTime;
+use chrono::{DateTime, NaiveDateTime, TimeZone, Utc};
+
+use crate::backend::{self, AudioBackend};
+use crate::{clock, duck, replay, sandbox, tasks, timer, worker};
 
 lazy_static! {
     static ref CURRENT_AUDIO: AtomicU64 = AtomicU64::new(0);
+    // the process ID alone can be reused by a later program, so the start time is included
+    static ref PROCESS_TAG: String = format!("{}_{:x}", process::id(), Utc::now().timestamp_millis());
+    static ref SOFT_MODE: AtomicBool = AtomicBool::new(false);
+    // the environment does not change while the program runs, so it is only detected once
+    static ref ENVIRONMENT: Environment = detect_environment();
+    static ref SOFT_MODE_ID: AtomicU64 = AtomicU64::new(0);
+    static ref DEFAULT_FILE_TYPE: Mutex<Option<FileType>> = Mutex::new(None);
+    static ref WRITE_LIMITER: Mutex<WriteLimiter> = Mutex::new(WriteLimiter {
+        min_interval: Duration::ZERO,
+        last_write: None,
+        pending: HashMap::new()
+    });
+}
+
+/// Prefix of the names that are generated for audio instances without a custom name.
+///
+/// Generated names are the prefix followed by the process ID, the time that the first name
+/// was generated, and a counter, so programs that run at the same time never generate the
+/// same name.
+pub const DEFAULT_NAME_PREFIX: &str = "rust_audio_";
+
+/// Environment variable that overrides the path of the file that commands are written to,
+/// which is `/tmp/audio` by default.
+///
+/// The environment variable is read every time a command is written, so it can be changed
+/// while the program is running, like in tests.
+pub const UPDATE_PATH_ENV_VAR: &str = "REPLIT_AUDIO_UPDATE_PATH";
+/// Environment variable that overrides the path of the status file, which is
+/// `/tmp/audioStatus.json` by default.
+///
+/// The environment variable is read every time the status file is read, so it can be
+/// changed while the program is running, like in tests.
+pub const STATUS_PATH_ENV_VAR: &str = "REPLIT_AUDIO_STATUS_PATH";
+// yyyy-mm-ddThh-mm-ss.sssssssssZ, with any number of fractional digits and without the Z
+const TIME_FORMAT: &str = "%FT%T%.f";
+const REPLIT_ENV_VAR: &str = "REPL_ID";
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+const STATUS_READ_ATTEMPTS: usize = 3;
+// doubled after each failed attempt
+const STATUS_RETRY_DELAY: Duration = Duration::from_millis(5);
+const DEFAULT_BUILD_TIMEOUT: Duration = Duration::from_secs(2);
+const FOLLOW_DEADBAND: f64 = 0.01;
+const FADE_STEP_INTERVAL: Duration = Duration::from_millis(50);
+const MAX_VOLUME: f64 = 1.0;
+// range of human hearing
+const MIN_PITCH: f64 = 20.0;
+const MAX_PITCH: f64 = 20_000.0;
+
+struct WriteLimiter {
+    min_interval: Duration,
+    last_write: Option<Instant>,
+    // latest update command for each audio source that is waiting to be written, keyed by
+    // the backend and the id of the audio source
+    pending: HashMap<backend::SourceKey, (Arc<dyn AudioBackend>, json::JsonValue)>
 }
 
-const AUDIO_UPDATE_PATH: &str = "/tmp/audio";
-const AUDIO_STATUS_PATH: &str = "/tmp/audioStatus.json";
-const TIME_FORMAT: &str = "%FT%T.%fZ"; // yyyy-mm-ddThh-mm-ss.sssssssssZ
+impl WriteLimiter {
+    fn wait_time(&self, now: Instant) -> Duration {
+        match self.last_write {
+            Some(t) => self.min_interval.checked_sub(now.saturating_duration_since(t)).unwrap_or(Duration::ZERO),
+            None => Duration::ZERO
+        }
+    }
+}
 
 /// Used to play an audio file or tone and create an `Audio` instance.
+///
+/// A builder can be configured once and then cloned for each audio instance that differs
+/// in a few settings. Clones share the backend and the completion sender.
+#[derive(Clone)]
 pub struct AudioBuilder {
-    name: Option<String>,
-    audio_type: AudioType,
-    volume: f64,
-    does_loop: bool,
-    loop_count: i64
+    pub(crate) name: Option<String>,
+    pub(crate) audio_type: AudioType,
+    pub(crate) volume: f64,
+    pub(crate) pan: f64,
+    pub(crate) does_loop: bool,
+    pub(crate) loop_count: i64,
+    pub(crate) priority: u8,
+    pub(crate) completion_sender: Option<Sender<u64>>,
+    pub(crate) backend: Arc<dyn AudioBackend>,
+    pub(crate) timeout: Duration,
+    pub(crate) play_for: Option<Duration>,
+    // something that must live as long as the builder and the audio instances played with
+    // it, like a downloaded file that is removed once it is dropped
+    pub(crate) keep_alive: Option<Arc<dyn Any + Send + Sync>>
 }
 
 /// A struct providing access to some currently playing audio instance.
+///
+/// Cloning an `Audio` does not play the audio instance again. Both clones refer to the
+/// same audio instance.
+///
+/// `Audio` structs are equal and hash the same if they have the same ID (see `get_id`), so
+/// they can be used as keys of a `HashMap`. Audio instances with different backends (see
+/// `AudioBuilder::backend`) can have the same ID.
+#[derive(Clone)]
 pub struct Audio {
     id: u64,
-    audio_type: AudioType
+    // the name that the audio instance was played with, which never changes
+    name: String,
+    audio_type: AudioType,
+    backend: Arc<dyn AudioBackend>,
+    // the loop count that the audio instance was played with, which is 0 if it does not loop
+    configured_loop_count: i64,
+    // the status from the last call to refresh
+    cached: Option<AudioStatus>,
+    // the volume before the audio instance was muted, if it is muted
+    muted_volume: Option<f64>,
+    // see AudioBuilder, which is only held so it is dropped with the last clone
+    _keep_alive: Option<Arc<dyn Any + Send + Sync>>
+}
+
+/// A struct for an audio instance that was played with `AudioBuilder::fire`, but may not
+/// have begun playing yet.
+pub struct PendingAudio {
+    name: String,
+    // the audio instance, with an id that is only known once it begins playing
+    audio: Audio,
+    // taken once the audio instance is found, so they only run once
+    hooks: Mutex<Option<StartHooks>>
+}
+
+/// A struct for an audio instance that is being played with `AudioBuilder::build_async` in a
+/// background task.
+///
+/// Dropping the handle does not stop the audio instance or the background task.
+pub struct BuildHandle {
+    name: String,
+    rx: Receiver<AudioResult<Audio>>,
+    // set once is_finished receives the result, so join can still return it
+    result: Mutex<Option<AudioResult<Audio>>>
+}
+
+// things to do once an audio instance begins playing
+struct StartHooks {
+    priority: u8,
+    // only set if the audio instance can finish
+    completion_sender: Option<Sender<u64>>,
+    play_for: Option<Duration>
+}
+
+impl StartHooks {
+    fn run(&self, audio: &Audio) {
+        duck::register(&audio.backend, audio.id, self.priority);
+
+        if let Some(tx) = &self.completion_sender {
+            let tx = tx.clone();
+            let audio = audio.clone();
+
+            let cancelled = tasks::register(&audio.backend, audio.id);
+
+            // a dedicated thread, since waiting could tie up a worker thread for a long time
+            thread::spawn(move || {
+                match audio.wait_until_finished_or_cancelled(&cancelled) {
+                    // the receiver may have been dropped, which is fine
+                    Ok(true) => { let _ = tx.send(audio.id); },
+                    Ok(false) => (),
+                    Err(e) => log::warn!("Error in waiting for audio source {} to finish. ({})", audio.id, e)
+                }
+
+                tasks::unregister(&audio.backend, audio.id, &cancelled);
+            });
+        }
+
+        if let Some(duration) = self.play_for {
+            let audio = audio.clone();
+            let cancelled = tasks::register(&audio.backend, audio.id);
+
+            timer::schedule(duration, move || {
+                if !cancelled.load(Ordering::SeqCst) {
+                    if let Err(e) = write_stop(&audio.backend, audio.id) {
+                        log::warn!("Error in stopping audio source {} after playing it for {:?}. ({})", audio.id, duration, e);
+                    }
+                }
+
+                tasks::unregister(&audio.backend, audio.id, &cancelled);
+            });
+        }
+    }
+}
+
+/// A snapshot of the status of an audio instance.
+///
+/// Each getter of `Audio` reads the status file again, so `Audio::status` should be used
+/// to get several fields that are consistent with each other from a single read.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AudioStatus {
+    pub id: u64,
+    pub name: String,
+    pub volume: f64,
+    /// Duration in milliseconds.
+    pub duration: u64,
+    /// Remaining time in milliseconds.
+    pub remaining: u64,
+    pub paused: bool,
+    /// Number of times the audio instance will loop. Negative means an infinite loop.
+    pub loop_count: i64,
+    /// Start time in UTC, like `start_time_utc`.
+    pub start_time: NaiveDateTime,
+    /// End time of the current loop in UTC, like `end_time_utc`.
+    pub end_time: NaiveDateTime,
+    /// Fields in the status that are not modeled by the other fields, keyed by their name
+    /// in the status file.
+    ///
+    /// With the `serde` feature, these fields are not serialized, and they are empty after
+    /// deserializing.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub extra: HashMap<String, json::JsonValue>
 }
 
 /// A struct with fields for updating a currently playing audio instance.
+///
+/// The default values match the defaults of `AudioBuilder`, so fields that do not matter
+/// can be left out with `..Default::default()`.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AudioUpdate {
+    /// The volume must be between `0.0` and `1.0`, like in `AudioBuilder::volume`.
     pub volume: f64,
+    /// The pan must be between `-1.0` and `1.0`, like in `AudioBuilder::pan`.
+    pub pan: f64,
     pub paused: bool,
     pub does_loop: bool,
     /// Use a negative loop count to indicate an infinite loop.
     pub loop_count: i64
 }
 
-fn parse_status() -> AudioResult<json::JsonValue> {
-    let status_str = match fs::read_to_string(AUDIO_STATUS_PATH) {
-        Ok(s) => s,
-        Err(e) => Err(AudioError::new(format!("Error in reading {}. ({})", AUDIO_STATUS_PATH, e.to_string())))?
+impl Default for AudioUpdate {
+    fn default() -> Self {
+        AudioUpdate {
+            volume: 1.0,
+            pan: 0.0,
+            paused: false,
+            does_loop: false,
+            loop_count: -1
+        }
+    }
+}
+
+pub(crate) fn parse_status(backend: &Arc<dyn AudioBackend>) -> AudioResult<json::JsonValue> {
+    if sandbox::active() {
+        return Ok(sandbox::status());
+    }
+
+    with_retry(STATUS_READ_ATTEMPTS, || parse_status_str(&backend.read_status()?))
+}
+
+// call f until it succeeds or returns an error that is not retryable, for at most the
+// given number of attempts, with a short backoff in between
+fn with_retry<T, F: FnMut() -> AudioResult<T>>(attempts: usize, mut f: F) -> AudioResult<T> {
+    let mut delay = STATUS_RETRY_DELAY;
+
+    for _ in 1..attempts {
+        match f() {
+            Err(e) if e.is_retryable() => {
+                log::debug!("Retrying after a transient error. ({})", e);
+                clock::sleep(delay);
+                delay *= 2;
+            },
+            r => return r
+        }
+    }
+
+    f()
+}
+
+// the status file is written by another program, so it can be read while it is empty or
+// only partially written
+fn parse_status_str(status_str: &str) -> AudioResult<json::JsonValue> {
+    if status_str.trim().is_empty() {
+        return Err(AudioError::EmptyStatus);
+    }
+
+    match json::parse(status_str) {
+        Ok(status) => Ok(status),
+        Err(json::Error::UnexpectedEndOfJson) => Err(AudioError::TruncatedStatus),
+        Err(e) => Err(e.into())
+    }
+}
+
+pub(crate) fn write_command(backend: &Arc<dyn AudioBackend>, serialized: &json::JsonValue) -> AudioResult<()> {
+    let mut limiter = lock_after_wait(&WRITE_LIMITER);
+    write_now(&mut limiter, backend, serialized)
+}
+
+// lock the limiter once the minimum write interval has passed, without holding the lock
+// while sleeping, so a throttled write does not block other threads
+fn lock_after_wait(limiter: &Mutex<WriteLimiter>) -> MutexGuard<'_, WriteLimiter> {
+    loop {
+        let guard = limiter.lock().unwrap();
+        let wait = guard.wait_time(clock::now());
+
+        if wait == Duration::ZERO {
+            return guard;
+        }
+
+        // another thread may write in the meantime, so the wait is checked again
+        drop(guard);
+        clock::sleep(wait);
+    }
+}
+
+// update commands that are written faster than the minimum write interval are merged, so
+// only the latest update for each audio source is written once the interval passes
+fn write_update(backend: &Arc<dyn AudioBackend>, id: u64, serialized: json::JsonValue) -> AudioResult<()> {
+    let mut limiter = WRITE_LIMITER.lock().unwrap();
+    let wait = limiter.wait_time(clock::now());
+
+    if wait == Duration::ZERO {
+        return write_now(&mut limiter, backend, &serialized);
+    }
+
+    let flush_scheduled = !limiter.pending.is_empty();
+    limiter.pending.insert(backend::source_key(backend, id), (Arc::clone(backend), serialized));
+
+    if !flush_scheduled {
+        timer::schedule(wait, flush_pending_updates);
+    }
+
+    Ok(())
+}
+
+// stopping an audio source discards its pending update, so the update cannot undo the stop,
+// and cancels its background tasks
+pub(crate) fn write_stop(backend: &Arc<dyn AudioBackend>, id: u64) -> AudioResult<()> {
+    tasks::cancel(backend, id);
+
+    let serialized = object! {
+        ID: id,
+        Volume: 0.0,
+        Paused: true,
+        DoesLoop: false,
+        LoopCount: 0,
+        Stop: true
+    };
+
+    let mut limiter = lock_after_wait(&WRITE_LIMITER);
+    limiter.pending.remove(&backend::source_key(backend, id));
+    write_now(&mut limiter, backend, &serialized)
+}
+
+// pending updates are taken one at a time, so the ones that are still waiting can be
+// merged with newer updates or discarded by stops
+// writes a single pending update on the timer thread, and schedules itself again for the
+// rest, so the timer thread never sleeps for the minimum write interval
+fn flush_pending_updates() {
+    let mut limiter = WRITE_LIMITER.lock().unwrap();
+
+    // another write may have happened since this was scheduled
+    if limiter.wait_time(clock::now()) == Duration::ZERO {
+        let key = match limiter.pending.keys().next() {
+            Some(&key) => key,
+            None => return
+        };
+        let (backend, serialized) = limiter.pending.remove(&key).unwrap();
+
+        if let Err(e) = write_now(&mut limiter, &backend, &serialized) {
+            log::warn!("Error in writing update for audio source {}. ({})", key.1, e);
+        }
+    }
+
+    if !limiter.pending.is_empty() {
+        timer::schedule(limiter.wait_time(clock::now()), flush_pending_updates);
+    }
+}
+
+fn write_now(limiter: &mut WriteLimiter, backend: &Arc<dyn AudioBackend>, serialized: &json::JsonValue) -> AudioResult<()> {
+    limiter.last_write = Some(clock::now());
+    replay::log_command(serialized);
+
+    if sandbox::active() {
+        sandbox::apply(serialized);
+        return Ok(());
+    }
+
+    backend.write_command(&serialized.dump())
+}
+
+#[cfg(test)]
+thread_local! {
+    // soft mode for a single test, which does not affect tests on other threads
+    static TEST_SOFT_MODE: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+fn soft_mode_active() -> bool {
+    #[cfg(test)]
+    if TEST_SOFT_MODE.with(|m| m.get()) {
+        return true;
+    }
+
+    SOFT_MODE.load(Ordering::SeqCst) && !sandbox::active() && *ENVIRONMENT == Environment::Other
+}
+
+// returns whether an update to the audio source with the given id should be skipped
+fn soft_mode_skip_update(id: u64) -> bool {
+    let skip = soft_mode_active();
+
+    if skip {
+        log::info!("Not running on repl.it, so audio source {} will not be updated.", id);
+    }
+
+    skip
+}
+
+// times are in UTC, and times with a timezone offset are converted to UTC
+fn parse_time(time: &str, which: &str) -> AudioResult<NaiveDateTime> {
+    let field = if which == "start" { "StartTime" } else { "EndTime" };
+    let normalized = truncate_fraction(time.trim());
+
+    if let Ok(t) = DateTime::parse_from_rfc3339(&normalized) {
+        return Ok(t.naive_utc());
+    }
+
+    match NaiveDateTime::parse_from_str(normalized.trim_end_matches(['Z', 'z']), TIME_FORMAT) {
+        Ok(t) => Ok(t),
+        Err(e) => Err(invalid_field(field, format!("Error in parsing {} time {:?}. ({})", which, time, e)))
+    }
+}
+
+// chrono only parses up to nanoseconds, so extra fractional digits are dropped
+fn truncate_fraction(time: &str) -> String {
+    let start = match time.find('.') {
+        Some(i) => i + 1,
+        None => return time.to_owned()
     };
+    let digits = time[start..].bytes().take_while(u8::is_ascii_digit).count();
 
-    match json::parse(&status_str) {
-        Ok(s) => Ok(s),
-        Err(e) => Err(AudioError::new(format!("Error in parsing JSON. ({})", e.to_string())))
+    if digits > 9 {
+        format!("{}{}", &time[..start + 9], &time[start + digits..])
+    } else {
+        time.to_owned()
     }
 }
 
-fn get_status_by_id(id: u64) -> AudioResult<json::JsonValue> {
-    let mut status = parse_status()?;
+// the player can briefly report a negative remaining time at a loop boundary, so a
+// negative or missing remaining time is treated as nothing remaining
+pub(crate) fn remaining_from_status(status: &json::JsonValue) -> u64 {
+    status["Remaining"].as_f64().map_or(0, |r| r.max(0.0) as u64)
+}
+
+fn progress_from_status(status: &json::JsonValue) -> f64 {
+    let duration = status["Duration"].as_f64().unwrap_or(0.0);
+    let remaining = remaining_from_status(status) as f64;
+
+    if duration > 0.0 {
+        ((duration - remaining) / duration).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
 
-    match status["Sources"].members_mut().find(|s| s["ID"] == id) {
-        Some(o) => Ok(o.take()),
-        None => Err(AudioError::new(format!("No audio source found with id {}.", id)))
+fn finished_from_status(status: Option<&json::JsonValue>) -> bool {
+    match status {
+        // the remaining time can briefly be zero at a loop boundary
+        Some(s) => remaining_from_status(s) == 0 && s["Loop"].as_i64().unwrap_or(0) == 0,
+        None => true
     }
 }
 
-fn get_status_by_name(name: &str) -> AudioResult<json::JsonValue> {
-    let mut status = parse_status()?;
+fn is_mono_from_status(status: &json::JsonValue) -> Option<bool> {
+    status["Channels"].as_u64().map(|c| c == 1)
+}
+
+fn true_end_time_from(now: DateTime<Utc>, remaining: u64, duration: u64, loop_count: i64) -> Option<DateTime<Utc>> {
+    if loop_count < 0 {
+        return None;
+    }
+
+    let total = remaining as i64 + loop_count * duration as i64;
+    Some(now + chrono::Duration::milliseconds(total))
+}
 
-    match status["Sources"].members_mut().find(|s| s["Name"] == name) {
-        Some(o) => Ok(o.take()),
-        None => Err(AudioError::new(format!("No audio source found with name {}.", name)))
+fn get_status_by_id(backend: &Arc<dyn AudioBackend>, id: u64) -> AudioResult<json::JsonValue> {
+    match find_status_by_id(backend, id)? {
+        Some(o) => Ok(o),
+        None => Err(AudioError::SourceNotFound { id })
     }
 }
 
+pub(crate) fn find_status_by_id(backend: &Arc<dyn AudioBackend>, id: u64) -> AudioResult<Option<json::JsonValue>> {
+    let mut status = parse_status(backend)?;
+    Ok(status["Sources"].members_mut().find(|s| s["ID"] == id).map(|o| o.take()))
+}
+
 impl AudioBuilder {
     /// Create a new `AudioBuilder` with a certain `AudioType`, which describes either an
     /// audio file or a tone.
@@ -79,11 +514,48 @@ impl AudioBuilder {
             name: None,
             audio_type: audio_type.clone(),
             volume: 1.0,
+            pan: 0.0,
             does_loop: false,
-            loop_count: -1
+            loop_count: -1,
+            priority: 0,
+            completion_sender: None,
+            backend: backend::default_backend(),
+            timeout: DEFAULT_BUILD_TIMEOUT,
+            play_for: None,
+            keep_alive: None
         }
     }
 
+    /// Create a new `AudioBuilder` for an audio file of a certain `FileType`.
+    ///
+    /// This is a shorter way to write `AudioBuilder::new(&AudioType::File { file, path })`.
+    pub fn file<P: AsRef<str>>(file: FileType, path: P) -> Self {
+        AudioBuilder::new(&AudioType::File { file, path: path.as_ref().to_owned() })
+    }
+
+    /// Create a new `AudioBuilder` for a tone with a pitch in Hz and a duration in seconds.
+    ///
+    /// This is a shorter way to write
+    /// `AudioBuilder::new(&AudioType::Tone { tone, pitch, duration })`.
+    pub fn tone(tone: ToneType, pitch: f64, duration: f64) -> Self {
+        AudioBuilder::new(&AudioType::Tone { tone, pitch, duration })
+    }
+
+    /// Create a new `AudioBuilder` for an audio file, with the file type inferred from the
+    /// extension of the path (see `FileType::from_path`).
+    ///
+    /// An error is returned if the extension is missing or not recognized and there is no
+    /// default file type.
+    pub fn from_file<P: AsRef<str>>(path: P) -> AudioResult<AudioBuilder> {
+        Ok(AudioBuilder::new(&AudioType::from_path(path.as_ref())?))
+    }
+
+    /// Create a new `AudioBuilder` with the same settings as this one, but with a
+    /// different `AudioType`.
+    pub fn with_type(&self, audio_type: &AudioType) -> Self {
+        AudioBuilder { audio_type: audio_type.clone(), ..self.clone() }
+    }
+
     /// Set the name of the audio instance. Each audio instance should have a unique name.
     ///
     /// By default, a unique name is created for each audio instance.
@@ -95,12 +567,36 @@ impl AudioBuilder {
 
     /// Set the volume of the audio instance.
     ///
+    /// The volume must be between `0.0` and `1.0`, which is the range that the repl.it audio
+    /// playing program supports. Otherwise, playing the audio instance returns an error.
     /// By default, the volume is `1.0`.
     pub fn volume(mut self, volume: f64) -> Self {
         self.volume = volume;
         self
     }
 
+    /// Set the volume of the audio instance in decibels, like with `db_to_linear`.
+    ///
+    /// `0.0` dB is the full volume of `1.0`, and negative values attenuate the audio
+    /// instance. The volume is clamped to between `0.0` and `1.0`, so positive values are
+    /// the same as `0.0` dB.
+    pub fn volume_db(self, db: f64) -> Self {
+        self.volume(db_to_linear(db).min(MAX_VOLUME))
+    }
+
+    /// Set the stereo position of the audio instance, from `-1.0` for fully left to `1.0`
+    /// for fully right.
+    ///
+    /// The pan must be between `-1.0` and `1.0`. Otherwise, playing the audio instance
+    /// returns an error. The pan is sent to the repl.it audio playing program, but it may be
+    /// ignored by versions that do not support panning. Updates that keep the other settings
+    /// of the audio instance, like `Audio::set_volume`, only keep the pan if it is in the
+    /// status file. By default, the pan is `0.0`, which is centered.
+    pub fn pan(mut self, pan: f64) -> Self {
+        self.pan = pan;
+        self
+    }
+
     /// Set whether the audio instance loops.
     ///
     /// By default, the audio instance does not loop.
@@ -111,10 +607,66 @@ impl AudioBuilder {
 
     /// Set the number of times to loop.
     ///
-    /// `does_loop` should be used to allow looping of the audio instance.
-    /// By default, the audio instance loops forever.
+    /// A non-zero loop count also enables looping, like `does_loop(true)`, so looping can
+    /// still be disabled by calling `does_loop(false)` afterwards. A loop count of `0` does
+    /// not change whether the audio instance loops.
+    /// By default, the audio instance loops forever once looping is enabled.
     pub fn loop_count(mut self, loop_count: i64) -> Self {
         self.loop_count = loop_count;
+
+        if loop_count != 0 {
+            self.does_loop = true;
+        }
+
+        self
+    }
+
+    /// Set the priority of the audio instance, which is used for automatic ducking (see
+    /// `set_auto_duck`).
+    ///
+    /// By default, the priority is `0`, which is the lowest priority.
+    pub fn priority(mut self, level: u8) -> Self {
+        self.priority = level;
+        self
+    }
+
+    /// Set the backend that the audio instance is played with, which is also used by the
+    /// `Audio` struct of the audio instance.
+    ///
+    /// By default, the backend is `ReplitBackend`.
+    pub fn backend(mut self, backend: Arc<dyn AudioBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
+    /// Set how long `build` waits for the audio instance to begin playing before returning
+    /// an error.
+    ///
+    /// By default, the timeout is 2 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Stop the audio instance once it has played for `duration`, like playing only the
+    /// first few seconds of an audio file.
+    ///
+    /// A timer stops the audio instance like `Audio::stop_nowait`, so building does not
+    /// block, and waiting for the `duration` does not take up a thread. If the audio instance finishes before the `duration`, then nothing
+    /// happens. Cancelling the background tasks of the audio instance (see
+    /// `Audio::cancel_background_tasks`) also cancels the stop.
+    pub fn play_for(mut self, duration: Duration) -> Self {
+        self.play_for = Some(duration);
+        self
+    }
+
+    /// Send the ID of the audio instance on `tx` once it finishes playing.
+    ///
+    /// A background thread waits for the audio instance to finish. Nothing is sent for audio
+    /// instances that loop forever. If the receiver is dropped before the audio instance
+    /// finishes, then nothing is sent and the background thread exits quietly.
+    pub fn completion_sender(mut self, tx: Sender<u64>) -> Self {
+        self.completion_sender = Some(tx);
         self
     }
 
@@ -122,225 +674,2938 @@ impl AudioBuilder {
     /// update properties of the audio instance.
     ///
     /// This can be called multiple times to play a certain audio multiple times.
-    /// This will block until the audio instance begins playing.
+    /// This will block until the audio instance begins playing, or return an error if it
+    /// does not begin playing before the timeout (see `timeout`). If audio is disabled (see
+    /// `is_disabled`), then `AudioError::Disabled` is returned right away, and an error is
+    /// also returned right away if the status file cannot be read.
+    ///
+    /// When the audio instance does not begin playing before the timeout, the error is
+    /// `AudioError::Disabled` if audio was disabled in the meantime, `AudioError::NotRunning`
+    /// if the repl.it audio playing program reports that nothing is playing, and
+    /// `AudioError::Timeout` otherwise.
     pub fn build(&self) -> AudioResult<Audio> {
-        let name = match &self.name {
-            Some(n) => n.to_owned(),
-            // generate unique name
-            None => format!("rust_audio_{}", CURRENT_AUDIO.fetch_add(1, Ordering::SeqCst))
-        };
-
-        let serialized_args = match self.audio_type {
-            AudioType::File { ref path, .. } => object! {
-                Path: path.as_str()
-            },
-            AudioType::Tone { tone, pitch, duration } => object! {
-                WaveType: tone as u8,
-                Pitch: pitch,
-                Seconds: duration
-            }
-        };
+        self.build_with_name(&self.next_name())
+    }
 
-        let serialized = object! {
-            Name: name.as_str(),
-            Type: self.audio_type.as_str(),
-            Volume: self.volume,
-            DoesLoop: self.does_loop,
-            LoopCount: self.loop_count,
-            Args: serialized_args
-        };
+    /// Play the audio instance without waiting for it to begin playing, and return a
+    /// `PendingAudio` struct, which can be used to wait for it to begin playing later.
+    ///
+    /// This is useful for playing many audio instances quickly. The `PendingAudio` can
+    /// simply be dropped if there is no need to check that the audio instance began playing.
+    pub fn fire(&self) -> AudioResult<PendingAudio> {
+        let name = self.next_name();
+        self.start(&name)?;
+        Ok(PendingAudio { audio: self.audio_with_id(0, &name), name, hooks: Mutex::new(Some(self.start_hooks())) })
+    }
 
-        let mut file = match fs::OpenOptions::new().append(true).open(AUDIO_UPDATE_PATH) {
-            Ok(f) => f,
-            Err(e) => Err(AudioError::new(format!("Error in opening {}. ({})", AUDIO_UPDATE_PATH, e.to_string())))?
-        };
+    /// Play the audio instance like `build`, but in a background task, and return a
+    /// `BuildHandle` right away.
+    ///
+    /// The background task writes the command and waits for the audio instance to begin
+    /// playing, so this never blocks. Background tasks share a small pool of threads (see
+    /// `set_worker_threads`). `BuildHandle::join` resolves to the same
+    /// `AudioResult<Audio>` as `build`, including its errors, and `BuildHandle::is_finished`
+    /// can be polled to check whether it is ready. The name of the audio instance is
+    /// generated before this returns, so audio instances are named in the order that they
+    /// are built.
+    pub fn build_async(&self) -> BuildHandle {
+        let name = self.next_name();
+        let builder = self.clone();
+        let task_name = name.clone();
+        let (tx, rx) = mpsc::channel();
 
-        match write!(&mut file, "{}", serialized.dump()) {
-            Ok(_) => {
-                let start_time = Instant::now();
-                let time_out = Duration::from_secs(2);
+        // the sender is dropped without sending if the task panics
+        worker::execute(move || { let _ = tx.send(builder.build_with_name(&task_name)); });
 
-                while start_time.elapsed() <= time_out {
-                    if let Ok(status) = get_status_by_name(&name) {
-                        return Ok(Audio { id: status["ID"].as_u64().unwrap(), audio_type: self.audio_type.clone() });
-                    }
-                }
+        BuildHandle { name, rx, result: Mutex::new(None) }
+    }
 
-                Err(AudioError::new(format!("Timed out while waiting for {} to update.", AUDIO_STATUS_PATH)))
-            },
-            Err(e) => Err(AudioError::new(format!("Error in writing to {}. ({})", AUDIO_UPDATE_PATH, e.to_string())))
+    /// Play one audio instance for each of the `names` and return their `Audio` structs,
+    /// in the same order as the `names`.
+    ///
+    /// This ignores the name set through `name`. Before playing anything, this checks that
+    /// the `names` are distinct and that no audio source with any of the `names` already
+    /// exists, and returns `AudioError::DuplicateName` if that is not the case. If one of
+    /// the audio instances fails to play, then an error is returned and the audio instances
+    /// that were already played keep playing.
+    pub fn build_all_named(&self, names: &[&str]) -> AudioResult<Vec<Audio>> {
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(AudioError::DuplicateName { name: name.to_string() });
+            }
         }
-    }
-}
 
-/// Get whether there are any audio instances playing.
-pub fn is_running() -> AudioResult<bool> {
-    let status = parse_status()?;
-    Ok(status["Running"].as_bool().unwrap())
-}
+        if !soft_mode_active() {
+            let status = parse_status(&self.backend)?;
 
-/// Get whether the repl.it audio playing program is running.
-pub fn is_disabled() -> AudioResult<bool> {
-    let status = parse_status()?;
-    Ok(status["Disabled"].as_bool().unwrap())
-}
+            if let Some(name) = names.iter().find(|&&n| status["Sources"].members().any(|s| s["Name"] == n)) {
+                return Err(AudioError::DuplicateName { name: name.to_string() });
+            }
+        }
 
-impl Audio {
-    /// Get the name of the audio instance.
-    pub fn get_name(&self) -> AudioResult<String> {
-        let status = get_status_by_id(self.id)?;
-        Ok(status["Name"].as_str().unwrap().to_owned())
+        names.iter().map(|name| self.build_with_name(name)).collect()
     }
 
-    /// Get the `AudioType` of the audio instance.
-    pub fn get_type(&self) -> AudioType {
-        self.audio_type.clone()
-    }
+    fn start_hooks(&self) -> StartHooks {
+        let loops_forever = self.does_loop && self.loop_count < 0;
 
-    /// Get the volume level of the audio instance.
-    pub fn get_volume(&self) -> AudioResult<f64> {
-        let status = get_status_by_id(self.id)?;
-        Ok(status["Volume"].as_f64().unwrap())
+        StartHooks {
+            priority: self.priority,
+            completion_sender: self.completion_sender.clone().filter(|_| !loops_forever),
+            play_for: self.play_for
+        }
     }
 
-    /// Get the duration (in milliseconds) of the audio instance.
-    pub fn get_duration(&self) -> AudioResult<u64> {
-        let status = get_status_by_id(self.id)?;
-        Ok(status["Duration"].as_u64().unwrap())
+    fn audio_with_id(&self, id: u64, name: &str) -> Audio {
+        Audio {
+            id,
+            name: name.to_owned(),
+            audio_type: self.audio_type.clone(),
+            backend: Arc::clone(&self.backend),
+            configured_loop_count: if self.does_loop { self.loop_count } else { 0 },
+            cached: None,
+            muted_volume: None,
+            _keep_alive: self.keep_alive.clone()
+        }
     }
 
-    /// Get the remaining time (in milliseconds) of the audio instance.
-    pub fn get_remaining(&self) -> AudioResult<u64> {
-        let status = get_status_by_id(self.id)?;
-        Ok(status["Remaining"].as_u64().unwrap())
+    fn next_name(&self) -> String {
+        match &self.name {
+            Some(n) => n.to_owned(),
+            // generate unique name
+            None => generated_name(&PROCESS_TAG, CURRENT_AUDIO.fetch_add(1, Ordering::SeqCst))
+        }
     }
 
-    /// Get whether the audio instance is paused.
-    pub fn is_paused(&self) -> AudioResult<bool> {
-        let status = get_status_by_id(self.id)?;
-        Ok(status["Paused"].as_bool().unwrap())
+    /// Play the audio instance like `build`, but check the audio source with the ID
+    /// `id_hint` first when looking for the audio instance in the status file.
+    ///
+    /// If the audio source with the ID `id_hint` does not have the name of the audio
+    /// instance, then all audio sources are searched by name as usual.
+    pub fn build_with_id_hint(&self, id_hint: u64) -> AudioResult<Audio> {
+        let name = self.next_name();
+        self.start(&name)?;
+        let audio = self.audio_with_id(wait_for_start(&self.backend, &name, Some(id_hint), self.timeout)?, &name);
+        self.start_hooks().run(&audio);
+        Ok(audio)
     }
 
-    /// Get the number of times the audio instance will loop.
-    pub fn get_loop(&self) -> AudioResult<i64> {
-        let status = get_status_by_id(self.id)?;
-        Ok(status["Loop"].as_i64().unwrap())
+    fn build_with_name(&self, name: &str) -> AudioResult<Audio> {
+        self.start(name)?;
+        let audio = self.audio_with_id(wait_for_start(&self.backend, name, None, self.timeout)?, name);
+        self.start_hooks().run(&audio);
+        Ok(audio)
     }
 
-    /// Get the ID of the audio instance.
-    pub fn get_id(&self) -> u64 {
-        self.id
-    }
+    // write the command to play the audio instance, without waiting for it to begin playing
+    fn start(&self, name: &str) -> AudioResult<()> {
+        check_volume(self.volume)?;
+        check_pan(self.pan)?;
 
-    /// Get the end time of the audio instance.
-    pub fn get_end_time(&self) -> AudioResult<NaiveDateTime> {
-        let status = get_status_by_id(self.id)?;
+        if sandbox::active() {
+            sandbox::validate(&self.audio_type)?;
+        }
+
+        let serialized = serialize_build(name, &self.audio_type, self.volume, self.pan, self.does_loop, self.loop_count);
+
+        if soft_mode_active() {
+            log::info!("Not running on repl.it, so {} will not be played.", name);
+            return Ok(());
+        }
 
-        match NaiveDateTime::parse_from_str(status["EndTime"].as_str().unwrap(), TIME_FORMAT) {
-            Ok(t) => Ok(t),
-            Err(e) => Err(AudioError::new(format!("Error in parsing end time. ({})", e.to_string())))
+        // return early instead of waiting for an audio source that will never be added
+        if parse_status(&self.backend)?["Disabled"] == true {
+            return Err(AudioError::Disabled);
         }
+
+        write_command(&self.backend, &serialized)
     }
+}
 
-    /// Get the start time of the audio instance.
-    pub fn get_start_time(&self) -> AudioResult<NaiveDateTime> {
-        let status = get_status_by_id(self.id)?;
+fn start_timeout_error(status: Option<&json::JsonValue>, name: &str) -> AudioError {
+    match status {
+        // audio can be disabled after the command was written
+        Some(s) if s["Disabled"] == true => AudioError::Disabled,
+        // an audio playing program that is alive would have added the audio source
+        Some(s) if s["Running"] == false && s["Sources"].is_empty() => AudioError::NotRunning,
+        _ => AudioError::Timeout(format!("Timed out while waiting for {} to begin playing.", name))
+    }
+}
 
-        match NaiveDateTime::parse_from_str(status["StartTime"].as_str().unwrap(), TIME_FORMAT) {
-            Ok(t) => Ok(t),
-            Err(e) => Err(AudioError::new(format!("Error in parsing start time. ({})", e.to_string())))
+// the command that plays a new audio source
+fn serialize_build(name: &str, audio_type: &AudioType, volume: f64, pan: f64, does_loop: bool, loop_count: i64) -> json::JsonValue {
+    let serialized_args = match *audio_type {
+        AudioType::File { ref path, .. } => object! {
+            Path: path.as_str()
+        },
+        AudioType::Tone { tone, pitch, duration } => object! {
+            WaveType: tone as u8,
+            Pitch: pitch,
+            Seconds: duration
         }
+    };
+
+    object! {
+        Name: name,
+        Type: audio_type.as_str(),
+        Volume: volume,
+        Pan: pan,
+        DoesLoop: does_loop,
+        LoopCount: loop_count,
+        Args: serialized_args
+    }
+}
+
+// returns the id of the audio source with the given name once it appears in the status file
+pub(crate) fn wait_for_start(backend: &Arc<dyn AudioBackend>, name: &str, id_hint: Option<u64>, time_out: Duration) -> AudioResult<u64> {
+    if soft_mode_active() {
+        return Ok(SOFT_MODE_ID.fetch_add(1, Ordering::SeqCst));
     }
 
-    /// Update the audio instance by using the `AudioUpdate` struct.
-    pub fn update(&mut self, update: &AudioUpdate) -> AudioResult<()> {
-        let serialized = object! {
-            ID: self.id,
-            Volume: update.volume,
-            Paused: update.paused,
-            DoesLoop: update.does_loop,
-            LoopCount: update.loop_count
-        };
+    let start_time = clock::now();
 
-        let mut file = match fs::OpenOptions::new().append(true).open(AUDIO_UPDATE_PATH) {
-            Ok(f) => f,
-            Err(e) => Err(AudioError::new(format!("Error in opening {}. ({})", AUDIO_UPDATE_PATH, e.to_string())))?
-        };
+    // the last status that was read, which tells why the audio source did not appear
+    let mut last_status = None;
+
+    loop {
+        if let Ok(status) = parse_status(backend) {
+            let sources = &status["Sources"];
+            let hinted = id_hint.and_then(|id| sources.members().find(|s| s["ID"] == id && s["Name"] == name));
+
+            // a missing ID means that the status file is half written, so it is read again
+            if let Some(id) = hinted.or_else(|| sources.members().find(|s| s["Name"] == name)).and_then(|s| s["ID"].as_u64()) {
+                replay::log_started(name, id);
+                return Ok(id);
+            }
+
+            last_status = Some(status);
+        }
 
-        match write!(&mut file, "{}", serialized.dump()) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(AudioError::new(format!("Error in writing to {}. ({})", AUDIO_UPDATE_PATH, e.to_string())))
+        if clock::elapsed(start_time) > time_out {
+            return Err(start_timeout_error(last_status.as_ref(), name));
         }
+
+        // the audio playing program usually adds a source within tens of milliseconds, so
+        // polling every 10 ms adds little latency, while not taking the CPU away from the
+        // audio playing program that has to write the status file
+        clock::sleep(POLL_INTERVAL);
     }
 }
 
-/// Custom result type for playing audio.
-pub type AudioResult<T> = Result<T, AudioError>;
+impl PendingAudio {
+    /// Block until the audio instance begins playing and return an `Audio` struct for it,
+    /// or return an error if it does not begin playing before the `timeout`.
+    ///
+    /// This can be called multiple times. Settings that take effect once the audio instance
+    /// begins playing, like `AudioBuilder::play_for`, only take effect the first time that
+    /// it is found.
+    pub fn await_start(&self, timeout: Duration) -> AudioResult<Audio> {
+        let id = wait_for_start(&self.audio.backend, &self.name, None, timeout)?;
+        let audio = Audio { id, ..self.audio.clone() };
 
-/// Custom error type for audio errors.
-#[derive(Debug)]
-pub struct AudioError {
-    msg: String
-}
+        if let Some(hooks) = self.hooks.lock().unwrap().take() {
+            hooks.run(&audio);
+        }
 
-impl AudioError {
-    fn new(msg: String) -> AudioError {
-        AudioError { msg: msg }
+        Ok(audio)
     }
 }
 
-impl fmt::Display for AudioError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.msg)
+impl BuildHandle {
+    /// Block until the background task is done, and return the same `AudioResult<Audio>`
+    /// that `AudioBuilder::build` would have returned.
+    pub fn join(self) -> AudioResult<Audio> {
+        if let Some(r) = self.result.into_inner().unwrap() {
+            return r;
+        }
+
+        let name = self.name;
+        self.rx.recv().unwrap_or_else(|_| Err(AudioError::new(format!("The background task for playing {} panicked.", name))))
+    }
+
+    /// Get whether the background task is done, so `join` returns without blocking.
+    pub fn is_finished(&self) -> bool {
+        let mut result = self.result.lock().unwrap();
+
+        if result.is_some() {
+            return true;
+        }
+
+        match self.rx.try_recv() {
+            Ok(r) => {
+                *result = Some(r);
+                true
+            },
+            Err(TryRecvError::Disconnected) => true,
+            Err(TryRecvError::Empty) => false
+        }
+    }
+
+    /// Get the name of the audio instance that is being played.
+    pub fn name(&self) -> &str {
+        &self.name
     }
 }
 
-impl error::Error for AudioError {
-    fn description(&self) -> &str {
-        &self.msg
+/// Play each audio type in `items` after the previous one finishes, with the default
+/// settings of `AudioBuilder`.
+///
+/// This blocks until the last audio instance finishes. If an audio instance fails to begin
+/// playing, then the rest are not played and the error is returned.
+pub fn play_sequence(items: &[AudioType]) -> AudioResult<()> {
+    play_sequence_with_gap(items, Duration::ZERO)
+}
+
+/// Play each audio type in `items` like `play_sequence`, but wait for `gap` between the end
+/// of each audio instance and the start of the next one.
+pub fn play_sequence_with_gap(items: &[AudioType], gap: Duration) -> AudioResult<()> {
+    play_sequence_on(&backend::default_backend(), items, gap)
+}
+
+pub(crate) fn play_sequence_on(backend: &Arc<dyn AudioBackend>, items: &[AudioType], gap: Duration) -> AudioResult<()> {
+    for (i, audio_type) in items.iter().enumerate() {
+        if i > 0 {
+            clock::sleep(gap);
+        }
+
+        AudioBuilder::new(audio_type).backend(Arc::clone(backend)).build()?.wait()?;
     }
+
+    Ok(())
 }
 
-/// Type of audio to play: audio file or tone.
-#[derive(Debug, PartialEq, Clone)]
-pub enum AudioType {
-    File { file: FileType, path: String },
-    Tone { tone: ToneType, pitch: f64, duration: f64 }
+/// Get the name that will be generated for the next audio instance that is played without
+/// a custom name, without using it up.
+///
+/// Another thread can play an audio instance in between, so the name is not guaranteed to
+/// be used by the next audio instance played on this thread.
+pub fn peek_next_name() -> String {
+    generated_name(&PROCESS_TAG, CURRENT_AUDIO.load(Ordering::SeqCst))
 }
 
-impl AudioType {
-    fn as_str(&self) -> &'static str {
-        match self {
-            AudioType::File { file, .. } => file.as_str(),
-            AudioType::Tone { .. } => "tone"
+fn generated_name(process_tag: &str, counter: u64) -> String {
+    format!("{}{}_{}", DEFAULT_NAME_PREFIX, process_tag, counter)
+}
+
+/// Block until the repl.it audio playing program is ready, or return an error if it is not
+/// ready after the `timeout`.
+///
+/// Right after a repl starts, the status file may not exist yet, so playing audio may fail.
+/// This can be called once at the start of a program to wait for the status file to exist
+/// and contain the expected fields. In soft mode (see `set_soft_mode`), this returns
+/// immediately when not running on repl.it.
+pub fn wait_until_ready(timeout: Duration) -> AudioResult<()> {
+    if soft_mode_active() {
+        return Ok(());
+    }
+
+    wait_until_ready_on(&backend::default_backend(), &backend::status_path(), timeout)
+}
+
+fn wait_until_ready_on(backend: &Arc<dyn AudioBackend>, status_path: &str, timeout: Duration) -> AudioResult<()> {
+    let start_time = clock::now();
+
+    loop {
+        if let Ok(status) = parse_status(backend) {
+            if status["Sources"].is_array() && status["Running"].is_boolean() && status["Disabled"].is_boolean() {
+                return Ok(());
+            }
+        }
+
+        if clock::elapsed(start_time) > timeout {
+            return Err(AudioError::Timeout(format!("Timed out while waiting for {} to be ready.", status_path)));
         }
+
+        clock::sleep(POLL_INTERVAL);
     }
 }
 
-/// Supported audio file formats.
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum FileType {
-    Wav,
-    Aiff,
-    Mp3
+/// Set the minimum amount of time between writes to the repl.it audio playing program.
+///
+/// This prevents rapid updates, like the steps of a fade, from overwhelming the audio
+/// playing program. Playing audio waits until the interval has passed since the previous
+/// write. Updates to an audio instance that come too early are merged, so only the latest
+/// update for each audio instance is written once the interval has passed. Errors in
+/// writing merged updates are logged through the `log` crate instead of being returned
+/// by `Audio::update`.
+///
+/// By default, the minimum write interval is zero, so every write happens immediately.
+pub fn set_min_write_interval(interval: Duration) {
+    WRITE_LIMITER.lock().unwrap().min_interval = interval;
 }
 
-impl FileType {
-    fn as_str(&self) -> &'static str {
-        match self {
-            FileType::Wav => "wav",
-            FileType::Aiff => "aiff",
-            FileType::Mp3 => "mp3"
+/// Get the environment that the program is running in.
+///
+/// The environment is `Environment::Replit` if the repl.it audio files exist or the
+/// `REPL_ID` environment variable is set, and `Environment::Other` otherwise.
+pub fn detect_environment() -> Environment {
+    if env::var_os(REPLIT_ENV_VAR).is_some()
+        || Path::new(&backend::update_path()).exists()
+        || Path::new(&backend::status_path()).exists() {
+        Environment::Replit
+    } else {
+        Environment::Other
+    }
+}
+
+/// Set whether playback calls should silently do nothing when not running on repl.it.
+///
+/// When soft mode is enabled and `detect_environment()` returns `Environment::Other`,
+/// `AudioBuilder::build` returns an `Audio` that is not backed by any audio source and
+/// methods that update an audio instance return `Ok` without doing anything. Each
+/// skipped call is logged through the `log` crate. Methods that read the status of an
+/// audio instance still return errors, since there is no status to read. The environment
+/// is only detected once, the first time that soft mode is checked.
+///
+/// By default, soft mode is disabled.
+pub fn set_soft_mode(enabled: bool) {
+    SOFT_MODE.store(enabled, Ordering::SeqCst);
+}
+
+/// Get whether there are any audio instances playing.
+pub fn is_running() -> AudioResult<bool> {
+    let status = parse_status(&backend::default_backend())?;
+    status_field(&status, "Running", json::JsonValue::as_bool)
+}
+
+/// Get whether audio is disabled in the repl, in which case nothing can be played.
+pub fn is_disabled() -> AudioResult<bool> {
+    let status = parse_status(&backend::default_backend())?;
+    status_field(&status, "Disabled", json::JsonValue::as_bool)
+}
+
+const STATUS_FIELDS: [&str; 9] = ["ID", "Name", "Volume", "Duration", "Remaining", "Paused", "Loop", "StartTime", "EndTime"];
+
+impl AudioStatus {
+    fn from_json(mut status: json::JsonValue) -> AudioResult<AudioStatus> {
+        let start_time = parse_time(status_field(&status, "StartTime", json::JsonValue::as_str)?, "start")?;
+        let end_time = parse_time(status_field(&status, "EndTime", json::JsonValue::as_str)?, "end")?;
+        let id = status_field(&status, "ID", json::JsonValue::as_u64)?;
+        let name = status_field(&status, "Name", json::JsonValue::as_str)?.to_owned();
+        let volume = status_field(&status, "Volume", json::JsonValue::as_f64)?;
+        let duration = status_field(&status, "Duration", json::JsonValue::as_u64)?;
+        let remaining = remaining_from_status(&status);
+        let paused = status_field(&status, "Paused", json::JsonValue::as_bool)?;
+        let loop_count = status_field(&status, "Loop", json::JsonValue::as_i64)?;
+
+        let extra = status.entries_mut()
+            .filter(|(k, _)| !STATUS_FIELDS.contains(k))
+            .map(|(k, v)| (k.to_owned(), v.take()))
+            .collect();
+
+        Ok(AudioStatus {
+            id,
+            name,
+            volume,
+            duration,
+            remaining,
+            paused,
+            loop_count,
+            start_time,
+            end_time,
+            extra
+        })
+    }
+
+    /// Get the `AudioType` of the audio source from the `Type` and `Args` fields in its
+    /// status, which are kept in `extra`.
+    ///
+    /// Unlike `Audio::get_type`, this is the type that the repl.it audio playing program
+    /// reports, and it also works for audio sources that were not played by this program.
+    /// An error is returned if the type is unknown or its arguments are missing, like after
+    /// deserializing with the `serde` feature.
+    pub fn audio_type(&self) -> AudioResult<AudioType> {
+        let null = json::JsonValue::Null;
+        audio_type_from_fields(self.extra.get("Type").unwrap_or(&null), self.extra.get("Args").unwrap_or(&null))
+    }
+
+    /// Get `start_time`, which is always in UTC, with its time zone.
+    pub fn start_time_utc(&self) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&self.start_time)
+    }
+
+    /// Get `end_time`, which is always in UTC, with its time zone.
+    pub fn end_time_utc(&self) -> DateTime<Utc> {
+        Utc.from_utc_datetime(&self.end_time)
+    }
+}
+
+/// A snapshot of all audio sources, which can be used to play them again later.
+#[derive(Clone)]
+pub struct MixerSnapshot {
+    pub sources: Vec<SourceSnapshot>,
+    // the backend that the snapshot was taken from, which the audio sources are restored to
+    backend: Arc<dyn AudioBackend>
+}
+
+/// A snapshot of a single audio source, as part of a `MixerSnapshot`.
+#[derive(Debug, Clone)]
+pub struct SourceSnapshot {
+    pub audio_type: AudioType,
+    pub volume: f64,
+    pub pan: f64,
+    pub paused: bool,
+    /// Number of times the audio source will loop. Negative means an infinite loop.
+    pub loop_count: i64,
+    /// Position (in milliseconds) of the audio source when the snapshot was taken.
+    pub position: u64
+}
+
+/// Get the contents of the status file as pretty-printed JSON, which is useful for
+/// debugging.
+pub fn dump_status_json() -> AudioResult<String> {
+    Ok(parse_status(&backend::default_backend())?.pretty(4))
+}
+
+/// Set the file type that is used when the file type cannot be inferred from the extension
+/// of a path, like in `FileType::from_path`.
+///
+/// This is useful when the repl.it audio playing program can figure out the format of the
+/// file on its own. By default, there is no default file type, so inferring the file type
+/// of a path with a missing or unknown extension returns an error.
+pub fn set_default_file_type(file: Option<FileType>) {
+    *DEFAULT_FILE_TYPE.lock().unwrap() = file;
+}
+
+fn file_type_from_path(path: &Path, default: Option<FileType>) -> AudioResult<FileType> {
+    let extension = path.extension().and_then(|e| e.to_str()).map(|e| e.to_ascii_lowercase());
+
+    let file = match extension.as_deref() {
+        Some("wav") => Some(FileType::Wav),
+        Some("aiff") | Some("aif") => Some(FileType::Aiff),
+        Some("mp3") => Some(FileType::Mp3),
+        Some("ogg") => Some(FileType::Ogg),
+        Some("flac") => Some(FileType::Flac),
+        _ => None
+    };
+
+    match file.or(default) {
+        Some(f) => Ok(f),
+        None => Err(AudioError::new(format!("Cannot infer the file type of {}.", path.display())))
+    }
+}
+
+/// Stop every audio source whose status matches `pred`, and return the number of audio
+/// sources that were stopped.
+///
+/// The status file is read once, and each audio source is stopped by writing an update
+/// that sets `Stop` to `true`, pauses the audio source, mutes it, and turns off looping.
+/// In soft mode (see `set_soft_mode`), this does nothing when not running on repl.it and
+/// returns `0`.
+pub fn stop_where<F: Fn(&AudioStatus) -> bool>(pred: F) -> AudioResult<usize> {
+    if soft_mode_active() {
+        return Ok(0);
+    }
+
+    let backend = backend::default_backend();
+    let mut count = 0;
+
+    for source in sources_from_status(parse_status(&backend)?)? {
+        if pred(&source) {
+            write_stop(&backend, source.id)?;
+            count += 1;
         }
     }
+
+    Ok(count)
 }
 
-/// Supported tone types.
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum ToneType {
-    Sine = 0,
-    Triangle = 1,
-    Saw = 2,
-    Square = 3
+/// Stop every audio source in the status file, including audio sources that were not played
+/// by this program.
+///
+/// Each audio source is stopped like with `Audio::stop`. If there are no audio sources,
+/// then this does nothing. If stopping some audio sources fails, then the rest are still
+/// stopped, and the returned error lists all of the failures. In soft mode (see
+/// `set_soft_mode`), this does nothing when not running on repl.it.
+pub fn stop_all() -> AudioResult<()> {
+    if soft_mode_active() {
+        return Ok(());
+    }
+
+    let backend = backend::default_backend();
+    let status = parse_status(&backend)?;
+    let errors = status["Sources"].members()
+        .filter_map(|s| s["ID"].as_u64())
+        .filter_map(|id| write_stop(&backend, id).err().map(|e| format!("{}: {}", id, e)))
+        .collect::<Vec<_>>();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AudioError::new(format!("Error in stopping audio sources. ({})", errors.join("; "))))
+    }
+}
+
+/// Get the status of every audio source in the status file, including audio sources that
+/// were not played by this program.
+///
+/// The audio sources are in the same order as in the status file. If there are no audio
+/// sources, then the list is empty.
+pub fn list_sources() -> AudioResult<Vec<AudioStatus>> {
+    sources_from_status(parse_status(&backend::default_backend())?)
+}
+
+fn sources_from_status(mut status: json::JsonValue) -> AudioResult<Vec<AudioStatus>> {
+    status["Sources"].members_mut().map(|s| AudioStatus::from_json(s.take())).collect()
+}
+
+/// Take a snapshot of all audio sources that are currently playing.
+///
+/// The type of each audio source is read from the `Type` and `Args` fields in its
+/// status, which have the same layout as the command written by `AudioBuilder::build`.
+/// Audio sources whose type cannot be determined from their status are skipped, and a
+/// warning is logged through the `log` crate.
+pub fn snapshot_all() -> AudioResult<MixerSnapshot> {
+    snapshot_all_from(backend::default_backend())
+}
+
+/// Take a snapshot of all audio sources that are currently playing on `backend`, like
+/// `snapshot_all`.
+///
+/// Restoring the snapshot plays the audio sources on the same backend.
+pub fn snapshot_all_from(backend: Arc<dyn AudioBackend>) -> AudioResult<MixerSnapshot> {
+    let status = parse_status(&backend)?;
+    Ok(snapshot_from_status(backend, &status))
+}
+
+fn snapshot_from_status(backend: Arc<dyn AudioBackend>, status: &json::JsonValue) -> MixerSnapshot {
+    let sources = status["Sources"].members().filter_map(|s| {
+        match source_snapshot_from_status(s) {
+            Ok(source) => Some(source),
+            Err(e) => {
+                log::warn!("Skipping audio source {} in snapshot. ({})", s["ID"], e);
+                None
+            }
+        }
+    }).collect();
+
+    MixerSnapshot { sources, backend }
+}
+
+fn source_snapshot_from_status(status: &json::JsonValue) -> AudioResult<SourceSnapshot> {
+    let duration = status["Duration"].as_u64().unwrap_or(0);
+    let remaining = remaining_from_status(status);
+
+    Ok(SourceSnapshot {
+        audio_type: audio_type_from_status(status)?,
+        volume: status_field(status, "Volume", json::JsonValue::as_f64)?,
+        // the status file may not have the pan
+        pan: status["Pan"].as_f64().unwrap_or(0.0),
+        paused: status_field(status, "Paused", json::JsonValue::as_bool)?,
+        loop_count: status_field(status, "Loop", json::JsonValue::as_i64)?,
+        position: duration.saturating_sub(remaining)
+    })
+}
+
+fn audio_type_from_status(status: &json::JsonValue) -> AudioResult<AudioType> {
+    audio_type_from_fields(&status["Type"], &status["Args"])
+}
+
+fn audio_type_from_fields(type_value: &json::JsonValue, args: &json::JsonValue) -> AudioResult<AudioType> {
+    let type_str = match type_value.as_str() {
+        Some(t) => t,
+        None => Err(invalid_field("Type", "Missing audio source type.".to_owned()))?
+    };
+
+    if type_str == "tone" {
+        let tone = args["WaveType"].as_u8().and_then(ToneType::from_u8);
+        let pitch = args["Pitch"].as_f64();
+        let duration = args["Seconds"].as_f64();
+
+        match (tone, pitch, duration) {
+            (Some(tone), Some(pitch), Some(duration)) => Ok(AudioType::Tone { tone, pitch, duration }),
+            _ => Err(invalid_field("Args", "Missing or invalid tone arguments.".to_owned()))
+        }
+    } else {
+        let file = match FileType::from_type_str(type_str) {
+            Some(f) => f,
+            None => Err(invalid_field("Type", format!("Unknown audio source type {}.", type_str)))?
+        };
+
+        match args["Path"].as_str() {
+            Some(path) => Ok(AudioType::File { file, path: path.to_owned() }),
+            None => Err(invalid_field("Args", "Missing audio file path.".to_owned()))
+        }
+    }
+}
+
+fn invalid_field(field: &str, msg: String) -> AudioError {
+    AudioError::InvalidStatusField { field: field.to_owned(), msg }
+}
+
+// the status file can be read while it is half written, so fields can be missing
+fn status_field<'a, T, F: Fn(&'a json::JsonValue) -> Option<T>>(status: &'a json::JsonValue, field: &str, get: F) -> AudioResult<T> {
+    match get(&status[field]) {
+        Some(v) => Ok(v),
+        None if status[field].is_null() => Err(invalid_field(field, format!("Missing {} field.", field))),
+        None => Err(invalid_field(field, format!("Invalid {} field {}.", field, status[field])))
+    }
+}
+
+impl fmt::Debug for MixerSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MixerSnapshot").field("sources", &self.sources).finish_non_exhaustive()
+    }
+}
+
+impl MixerSnapshot {
+    /// Play all the audio sources in the snapshot again and return their `Audio` structs.
+    ///
+    /// The volume, pan, paused state, and loop count of each audio source are restored, on
+    /// the backend that the snapshot was taken from. Since the repl.it audio playing program
+    /// cannot seek, each audio source is played again from its start, rather than from its
+    /// `position`.
+    pub fn restore(&self) -> AudioResult<Vec<Audio>> {
+        self.sources.iter().map(|source| {
+            let mut audio = AudioBuilder::new(&source.audio_type)
+                .volume(source.volume)
+                .pan(source.pan)
+                .loop_count(source.loop_count)
+                .backend(Arc::clone(&self.backend))
+                .build()?;
+
+            if source.paused {
+                audio.update(&AudioUpdate {
+                    volume: source.volume,
+                    pan: source.pan,
+                    paused: true,
+                    does_loop: source.loop_count != 0,
+                    loop_count: source.loop_count
+                })?;
+            }
+
+            Ok(audio)
+        }).collect()
+    }
 }
 
+impl Audio {
+    /// Get the name of the audio instance from the status file.
+    ///
+    /// The name never changes, so `name` should usually be used instead, since it does not
+    /// read the status file.
+    pub fn get_name(&self) -> AudioResult<String> {
+        Ok(self.status()?.name)
+    }
+
+    /// Get the name that the audio instance was played with, like the generated name when
+    /// no custom name was set, without reading the status file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the `AudioType` that the audio instance was played with.
+    ///
+    /// This does not read the status file. Use `get_status_type` to get the type that the
+    /// repl.it audio playing program reports.
+    pub fn get_type(&self) -> AudioType {
+        self.audio_type.clone()
+    }
+
+    /// Get the `AudioType` of the audio instance from the status file, like with
+    /// `AudioStatus::audio_type`.
+    pub fn get_status_type(&self) -> AudioResult<AudioType> {
+        self.status()?.audio_type()
+    }
+
+    /// Get the volume level of the audio instance.
+    pub fn get_volume(&self) -> AudioResult<f64> {
+        Ok(self.status()?.volume)
+    }
+
+    /// Get the volume level of the audio instance, both as is and in decibels, from a single
+    /// read of the status file.
+    ///
+    /// A volume of `0.0` is `f64::NEG_INFINITY` decibels.
+    pub fn get_volume_linear_and_db(&self) -> AudioResult<(f64, f64)> {
+        let status = get_status_by_id(&self.backend, self.id)?;
+        let volume = status_field(&status, "Volume", json::JsonValue::as_f64)?;
+        Ok((volume, linear_to_db(volume)))
+    }
+
+    /// Get whether the audio instance has a single channel, or `None` if the repl.it audio
+    /// playing program does not report the number of channels.
+    pub fn is_mono(&self) -> AudioResult<Option<bool>> {
+        let status = get_status_by_id(&self.backend, self.id)?;
+        Ok(is_mono_from_status(&status))
+    }
+
+    /// Get the current peak signal level of the audio instance, or `None` if the repl.it
+    /// audio playing program does not report it.
+    ///
+    /// Unlike the volume, this is the actual level of the audio that is playing.
+    pub fn get_peak(&self) -> AudioResult<Option<f64>> {
+        let status = get_status_by_id(&self.backend, self.id)?;
+        Ok(status["Peak"].as_f64())
+    }
+
+    /// Get the current RMS signal level of the audio instance, or `None` if the repl.it
+    /// audio playing program does not report it.
+    ///
+    /// Unlike the volume, this is the actual level of the audio that is playing.
+    pub fn get_rms(&self) -> AudioResult<Option<f64>> {
+        let status = get_status_by_id(&self.backend, self.id)?;
+        Ok(status["RMS"].as_f64())
+    }
+
+    /// Get the duration (in milliseconds) of the audio instance.
+    pub fn get_duration(&self) -> AudioResult<u64> {
+        Ok(self.status()?.duration)
+    }
+
+    /// Get the remaining time (in milliseconds) of the audio instance.
+    ///
+    /// If the status reports a negative remaining time, which can briefly happen when the
+    /// audio instance loops, then the remaining time is `0`.
+    pub fn get_remaining(&self) -> AudioResult<u64> {
+        Ok(self.status()?.remaining)
+    }
+
+    /// Get the duration of the audio instance as a `Duration`.
+    pub fn get_duration_as(&self) -> AudioResult<Duration> {
+        Ok(Duration::from_millis(self.get_duration()?))
+    }
+
+    /// Get the remaining time of the audio instance as a `Duration`.
+    ///
+    /// Like `get_remaining`, a negative remaining time in the status is treated as zero.
+    pub fn get_remaining_as(&self) -> AudioResult<Duration> {
+        Ok(Duration::from_millis(self.get_remaining()?))
+    }
+
+    /// Get whether the audio instance is paused.
+    pub fn is_paused(&self) -> AudioResult<bool> {
+        Ok(self.status()?.paused)
+    }
+
+    /// Get the number of times the audio instance will loop.
+    pub fn get_loop(&self) -> AudioResult<i64> {
+        Ok(self.status()?.loop_count)
+    }
+
+    /// Get the loop count that the audio instance was played with, which is `0` if it was
+    /// played without looping.
+    ///
+    /// Unlike `get_loop`, which counts down as the audio instance loops, this does not
+    /// change, so the number of loops that were completed is the difference between the two.
+    /// This does not read the status file, and updates to the loop count of the audio
+    /// instance are not reflected.
+    pub fn configured_loop_count(&self) -> i64 {
+        self.configured_loop_count
+    }
+
+    /// Get the number of times the audio instance will loop and the fraction (from `0.0` to
+    /// `1.0`) of the current loop that has been played.
+    ///
+    /// Both values are read from the same status, so they are consistent with each other.
+    /// For audio instances that loop forever, the loop count is negative.
+    pub fn loop_progress(&self) -> AudioResult<(i64, f64)> {
+        let status = get_status_by_id(&self.backend, self.id)?;
+        Ok((status_field(&status, "Loop", json::JsonValue::as_i64)?, progress_from_status(&status)))
+    }
+
+    /// Get the fraction (from `0.0` to `1.0`) of the current loop of the audio instance that
+    /// has been played.
+    ///
+    /// The duration and remaining time are read from the same status, so they are
+    /// consistent with each other. If the duration is `0`, then the progress is `0.0`.
+    pub fn get_progress(&self) -> AudioResult<f64> {
+        let status = get_status_by_id(&self.backend, self.id)?;
+        Ok(progress_from_status(&status))
+    }
+
+    /// Get the ID of the audio instance.
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+
+    /// Block the calling thread until the audio instance finishes playing.
+    ///
+    /// The status file is polled every 10 milliseconds, until the audio instance is no
+    /// longer in it, or until it has no time remaining and no loops left. An error is
+    /// returned if the status file cannot be read. For audio instances that loop forever,
+    /// this blocks forever, unless they are stopped from another thread.
+    pub fn wait(&self) -> AudioResult<()> {
+        self.wait_until_finished_or_cancelled(&AtomicBool::new(false))?;
+        Ok(())
+    }
+
+    // returns false if the wait was cancelled before the audio source finished
+    pub(crate) fn wait_until_finished_or_cancelled(&self, cancelled: &AtomicBool) -> AudioResult<bool> {
+        if soft_mode_active() {
+            return Ok(true);
+        }
+
+        loop {
+            if cancelled.load(Ordering::SeqCst) {
+                return Ok(false);
+            }
+
+            if self.is_finished()? {
+                return Ok(true);
+            }
+
+            clock::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Get whether the audio instance finished playing, which is when it is no longer in
+    /// the status file, or when it has no time remaining and no loops left.
+    ///
+    /// Unlike the other getters, an audio instance that is no longer in the status file is
+    /// not an error. Errors in reading or parsing the status file are still returned as
+    /// errors.
+    pub fn is_finished(&self) -> AudioResult<bool> {
+        Ok(finished_from_status(find_status_by_id(&self.backend, self.id)?.as_ref()))
+    }
+
+    /// Get the end time of the audio instance, in UTC.
+    ///
+    /// `get_end_time_utc` should usually be used instead, since the time zone is kept.
+    pub fn get_end_time(&self) -> AudioResult<NaiveDateTime> {
+        Ok(self.status()?.end_time)
+    }
+
+    /// Get the start time of the audio instance, in UTC.
+    ///
+    /// `get_start_time_utc` should usually be used instead, since the time zone is kept.
+    pub fn get_start_time(&self) -> AudioResult<NaiveDateTime> {
+        Ok(self.status()?.start_time)
+    }
+
+    /// Get the end time of the audio instance, which can be converted to local time.
+    pub fn get_end_time_utc(&self) -> AudioResult<DateTime<Utc>> {
+        Ok(self.status()?.end_time_utc())
+    }
+
+    /// Get the start time of the audio instance, which can be converted to local time.
+    pub fn get_start_time_utc(&self) -> AudioResult<DateTime<Utc>> {
+        Ok(self.status()?.start_time_utc())
+    }
+
+    /// Get the time when the audio instance will finish playing, including all of the
+    /// loops that are left, or `None` if it loops forever.
+    ///
+    /// Unlike `get_end_time`, which is the end of the current loop, this is computed from
+    /// the remaining time, the duration, and the number of loops left.
+    pub fn true_end_time(&self) -> AudioResult<Option<DateTime<Utc>>> {
+        let status = get_status_by_id(&self.backend, self.id)?;
+
+        Ok(true_end_time_from(
+            clock::utc_now(),
+            remaining_from_status(&status),
+            status_field(&status, "Duration", json::JsonValue::as_u64)?,
+            status_field(&status, "Loop", json::JsonValue::as_i64)?
+        ))
+    }
+
+    /// Get the status of the audio instance as pretty-printed JSON, exactly as it is in the
+    /// status file, which is useful for debugging.
+    pub fn dump_handle_json(&self) -> AudioResult<String> {
+        Ok(get_status_by_id(&self.backend, self.id)?.pretty(4))
+    }
+
+    /// Get a snapshot of the status of the audio instance.
+    ///
+    /// All fields are read from the status file at once, so they are consistent with
+    /// each other.
+    pub fn status(&self) -> AudioResult<AudioStatus> {
+        AudioStatus::from_json(get_status_by_id(&self.backend, self.id)?)
+    }
+
+    /// Read the status of the audio instance once and store it in the `Audio` struct, so it
+    /// can be read with the cached accessors, like `cached_volume`, without reading the
+    /// status file again.
+    ///
+    /// The cached status is stale until the next call to `refresh`: it does not change when
+    /// the audio instance keeps playing or is updated. If reading the status fails, then the
+    /// previously cached status is kept.
+    pub fn refresh(&mut self) -> AudioResult<()> {
+        self.cached = Some(self.status()?);
+        Ok(())
+    }
+
+    /// Get the status from the last call to `refresh`, or `None` if it was never called.
+    pub fn cached_status(&self) -> Option<&AudioStatus> {
+        self.cached.as_ref()
+    }
+
+    /// Get the volume level from the last call to `refresh`, or `None` if it was never
+    /// called.
+    pub fn cached_volume(&self) -> Option<f64> {
+        self.cached.as_ref().map(|s| s.volume)
+    }
+
+    /// Get the remaining time in milliseconds from the last call to `refresh`, or `None` if
+    /// it was never called.
+    pub fn cached_remaining(&self) -> Option<u64> {
+        self.cached.as_ref().map(|s| s.remaining)
+    }
+
+    /// Get whether the audio instance was paused at the last call to `refresh`, or `None` if
+    /// it was never called.
+    pub fn cached_paused(&self) -> Option<bool> {
+        self.cached.as_ref().map(|s| s.paused)
+    }
+
+    /// Get the loop count from the last call to `refresh`, or `None` if it was never called.
+    pub fn cached_loop_count(&self) -> Option<i64> {
+        self.cached.as_ref().map(|s| s.loop_count)
+    }
+
+    /// Block until `pred` holds for the status of the audio instance and return `true`, or
+    /// return `false` if it does not hold before the `timeout`.
+    ///
+    /// The status is polled every 10 milliseconds. An error is returned if the audio
+    /// instance is no longer in the status file.
+    pub fn wait_for<F: Fn(&AudioStatus) -> bool>(&self, pred: F, timeout: Duration) -> AudioResult<bool> {
+        let start_time = clock::now();
+
+        loop {
+            if pred(&self.status()?) {
+                return Ok(true);
+            }
+
+            if clock::elapsed(start_time) > timeout {
+                return Ok(false);
+            }
+
+            clock::sleep(POLL_INTERVAL);
+        }
+    }
+
+    /// Get a snapshot of the status of the audio instance, or `None` if the audio instance
+    /// is no longer in the status file because it finished playing.
+    ///
+    /// Unlike `status`, a finished audio instance is not an error. Errors in reading or
+    /// parsing the status file are still returned as errors.
+    pub fn try_status(&self) -> AudioResult<Option<AudioStatus>> {
+        match find_status_by_id(&self.backend, self.id)? {
+            Some(o) => Ok(Some(AudioStatus::from_json(o)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// Update the audio instance by using the `AudioUpdate` struct.
+    ///
+    /// An error is returned without updating the audio instance if the volume is not
+    /// between `0.0` and `1.0`.
+    pub fn update(&mut self, update: &AudioUpdate) -> AudioResult<()> {
+        update_source(&self.backend, self.id, update)
+    }
+
+    /// Stop the audio instance, or return an error if it is no longer in the status file.
+    ///
+    /// This writes the following update, where `ID` is the ID of the audio instance:
+    ///
+    /// ```text
+    /// {"ID": 1, "Volume": 0.0, "Paused": true, "DoesLoop": false, "LoopCount": 0, "Stop": true}
+    /// ```
+    ///
+    /// The repl.it audio playing program removes the audio instance from the status file
+    /// shortly after, so methods that read its status, like `is_paused` and `get_remaining`,
+    /// return errors once it is removed. The pause, volume, and loop fields make sure that
+    /// the audio instance goes silent even if the `Stop` field is ignored. This does not wait
+    /// for the audio instance to be removed (see `stop_and_wait`).
+    pub fn stop(&mut self) -> AudioResult<()> {
+        if soft_mode_skip_update(self.id) {
+            return Ok(());
+        }
+
+        get_status_by_id(&self.backend, self.id)?;
+        write_stop(&self.backend, self.id)
+    }
+
+    /// Stop the audio instance without waiting for it to be removed from the status file.
+    ///
+    /// This writes the same update as `stop`, but does not check that the audio instance is
+    /// still in the status file.
+    pub fn stop_nowait(&mut self) -> AudioResult<()> {
+        if soft_mode_skip_update(self.id) {
+            return Ok(());
+        }
+
+        write_stop(&self.backend, self.id)
+    }
+
+    /// Stop the audio instance and block until it is removed from the status file.
+    ///
+    /// An error is returned if the audio instance is still in the status file after the
+    /// `timeout`, which means that the repl.it audio playing program ignored the command.
+    pub fn stop_and_wait(&mut self, timeout: Duration) -> AudioResult<()> {
+        if soft_mode_skip_update(self.id) {
+            return Ok(());
+        }
+
+        write_stop(&self.backend, self.id)?;
+        let start_time = clock::now();
+
+        while find_status_by_id(&self.backend, self.id)?.is_some() {
+            if clock::elapsed(start_time) > timeout {
+                return Err(AudioError::Timeout(format!("Audio source {} was not stopped.", self.id)));
+            }
+
+            clock::sleep(POLL_INTERVAL);
+        }
+
+        Ok(())
+    }
+
+    /// Turn this handle into an `AudioGuard`, which stops the audio instance when it is
+    /// dropped.
+    ///
+    /// This is useful for sound effects that should only play within some scope. Dropping
+    /// an `Audio` itself never stops the audio instance.
+    pub fn into_guard(self) -> AudioGuard {
+        AudioGuard { audio: self }
+    }
+
+    /// Make the volume of the audio instance follow the values returned by `source`, and
+    /// return a `VolumeFollower` that stops following when it is dropped.
+    ///
+    /// A background thread calls `source` every `interval` and clamps its value to the
+    /// range `[0.0, 1.0]`, and values that are not finite, like `f64::NAN`, are skipped. The
+    /// volume is only updated when the value changes by more than a small amount, to avoid
+    /// flooding the audio playing program with updates. The thread stops on its own if the
+    /// volume cannot be updated, like when the audio instance ends.
+    pub fn follow<F: FnMut() -> f64 + Send + 'static>(&self, interval: Duration, mut source: F) -> VolumeFollower {
+        let id = self.id;
+        let backend = Arc::clone(&self.backend);
+        let stop = tasks::register(&backend, id);
+        let thread_stop = Arc::clone(&stop);
+
+        // a dedicated thread, since following runs until it is stopped
+        let handle = thread::spawn(move || {
+            let mut prev_volume: Option<f64> = None;
+
+            while !thread_stop.load(Ordering::SeqCst) {
+                let volume = source();
+
+                // a single bad value should not stop following
+                if !volume.is_finite() {
+                    clock::sleep(interval);
+                    continue;
+                }
+
+                let volume = volume.clamp(0.0, 1.0);
+
+                if prev_volume.is_none_or(|v| (v - volume).abs() > FOLLOW_DEADBAND) {
+                    if let Err(e) = set_source_volume(&backend, id, volume) {
+                        log::debug!("Stopped following for audio source {}. ({})", id, e);
+                        break;
+                    }
+
+                    prev_volume = Some(volume);
+                }
+
+                clock::sleep(interval);
+            }
+
+            tasks::unregister(&backend, id, &thread_stop);
+        });
+
+        VolumeFollower { stop, handle: Some(handle) }
+    }
+
+    /// Stop all background tasks for the audio instance, without stopping the audio
+    /// instance itself.
+    ///
+    /// This stops volume following (see `follow`) and waiting to send the ID of the audio
+    /// instance once it finishes (see `AudioBuilder::completion_sender`), so they do not
+    /// fight with updates made afterwards. Stopping the audio instance, like with
+    /// `stop_nowait`, `stop_and_wait`, or `stop_where`, also stops all of its background
+    /// tasks.
+    pub fn cancel_background_tasks(&self) {
+        tasks::cancel(&self.backend, self.id);
+    }
+
+    /// Set the number of times the audio instance will loop, while keeping its current
+    /// volume, paused state, and whether it loops.
+    ///
+    /// The update command sent to the player does not carry a playback position, so the
+    /// player keeps playing from the current position instead of restarting the audio.
+    pub fn set_loop_count_preserving_position(&mut self, loop_count: i64) -> AudioResult<()> {
+        if soft_mode_skip_update(self.id) {
+            return Ok(());
+        }
+
+        let status = get_status_by_id(&self.backend, self.id)?;
+        self.update(&AudioUpdate { loop_count, ..update_from_status(&status)? })
+    }
+
+    /// Set the volume of the audio instance, while keeping its paused state and loop
+    /// settings.
+    ///
+    /// The volume must be between `0.0` and `1.0`, which is the range that the repl.it
+    /// audio playing program supports, or an error is returned without updating the audio
+    /// instance. An error is also returned if the audio instance is no longer in the status
+    /// file.
+    pub fn set_volume(&mut self, volume: f64) -> AudioResult<()> {
+        check_volume(volume)?;
+        set_source_volume(&self.backend, self.id, volume)
+    }
+
+    /// Mute the audio instance by setting its volume to `0.0`, and remember its current
+    /// volume so it can be restored with `unmute`.
+    ///
+    /// The volume is remembered in the `Audio` struct, so it is not lost even though the
+    /// status file shows a volume of `0.0` afterwards. Muting an audio instance that is
+    /// already muted does nothing.
+    pub fn mute(&mut self) -> AudioResult<()> {
+        if soft_mode_skip_update(self.id) || self.muted_volume.is_some() {
+            return Ok(());
+        }
+
+        let volume = self.get_volume()?;
+        self.set_volume(0.0)?;
+        self.muted_volume = Some(volume);
+        Ok(())
+    }
+
+    /// Restore the volume that the audio instance had before it was muted with `mute`, or
+    /// set it to `1.0` if it was not muted.
+    pub fn unmute(&mut self) -> AudioResult<()> {
+        self.set_volume(self.muted_volume.unwrap_or(MAX_VOLUME))?;
+        self.muted_volume = None;
+        Ok(())
+    }
+
+    /// Get whether the audio instance was muted with `mute` and not unmuted since.
+    pub fn is_muted(&self) -> bool {
+        self.muted_volume.is_some()
+    }
+
+    /// Set the volume of the audio instance in decibels, like with `set_volume`.
+    ///
+    /// `0.0` dB is the full volume of `1.0`, and negative values attenuate the audio
+    /// instance. The volume is clamped to between `0.0` and `1.0`, so positive values are
+    /// the same as `0.0` dB.
+    pub fn set_volume_db(&mut self, db: f64) -> AudioResult<()> {
+        self.set_volume(db_to_linear(db).min(MAX_VOLUME))
+    }
+
+    /// Gradually change the volume of the audio instance to `target_volume` over the
+    /// `duration`, with one update every 50 ms.
+    ///
+    /// This blocks for the `duration` and leaves the volume at exactly `target_volume`. See
+    /// `fade_to_with_steps` for details.
+    pub fn fade_to(&mut self, target_volume: f64, duration: Duration) -> AudioResult<()> {
+        let steps = (duration.as_secs_f64() / FADE_STEP_INTERVAL.as_secs_f64()).ceil().max(1.0) as u32;
+        self.fade_to_with_steps(target_volume, duration, steps)
+    }
+
+    /// Gradually change the volume of the audio instance to `target_volume` over the
+    /// `duration`, with `steps` evenly spaced updates.
+    ///
+    /// The volume changes linearly from its current value, and the last update sets it to
+    /// exactly `target_volume` at the end of the `duration`. Each update keeps the paused
+    /// state and loop settings of the audio instance. This blocks for the `duration`. The
+    /// target volume must be between `0.0` and `1.0`, and an error is returned if the audio
+    /// instance is no longer in the status file at any step.
+    pub fn fade_to_with_steps(&mut self, target_volume: f64, duration: Duration, steps: u32) -> AudioResult<()> {
+        check_volume(target_volume)?;
+
+        if soft_mode_skip_update(self.id) {
+            return Ok(());
+        }
+
+        let steps = steps.max(1);
+        let start_volume = self.get_volume()?;
+        let start_time = clock::now();
+
+        for i in 1..=steps {
+            let fraction = i as f64 / steps as f64;
+            clock::sleep(duration.mul_f64(fraction).saturating_sub(clock::elapsed(start_time)));
+
+            let volume = if i == steps {
+                target_volume
+            } else {
+                start_volume + (target_volume - start_volume) * fraction
+            };
+            set_source_volume(&self.backend, self.id, volume)?;
+        }
+
+        Ok(())
+    }
+
+    /// Fade the volume of the audio instance to `0.0` over the `duration` like `fade_to`,
+    /// and then stop it.
+    ///
+    /// If the audio instance finishes on its own before or during the fade, then this
+    /// returns `Ok` without stopping anything.
+    pub fn fade_out_and_stop(&mut self, duration: Duration) -> AudioResult<()> {
+        match self.fade_to(0.0, duration) {
+            Ok(()) => self.stop_nowait(),
+            Err(AudioError::SourceNotFound { .. }) => Ok(()),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Pause the audio instance, while keeping its current volume and loop settings.
+    ///
+    /// This does nothing if the audio instance is already paused. An error is returned if
+    /// the audio instance is no longer in the status file.
+    pub fn pause(&mut self) -> AudioResult<()> {
+        self.set_paused(true)
+    }
+
+    /// Resume the audio instance after it was paused, while keeping its current volume and
+    /// loop settings.
+    ///
+    /// This does nothing if the audio instance is not paused. An error is returned if the
+    /// audio instance is no longer in the status file.
+    pub fn resume(&mut self) -> AudioResult<()> {
+        self.set_paused(false)
+    }
+
+    fn set_paused(&mut self, paused: bool) -> AudioResult<()> {
+        if soft_mode_skip_update(self.id) {
+            return Ok(());
+        }
+
+        let status = get_status_by_id(&self.backend, self.id)?;
+        let update = update_from_status(&status)?;
+
+        if update.paused == paused {
+            return Ok(());
+        }
+
+        self.update(&AudioUpdate { paused, ..update })
+    }
+}
+
+/// A guard returned by `Audio::follow`, which stops the volume from following its source
+/// when it is dropped.
+pub struct VolumeFollower {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>
+}
+
+impl Drop for VolumeFollower {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// A guard returned by `Audio::into_guard`, which stops the audio instance when it is
+/// dropped.
+///
+/// The guard dereferences to the `Audio`, so it can be used like the handle.
+pub struct AudioGuard {
+    audio: Audio
+}
+
+impl Deref for AudioGuard {
+    type Target = Audio;
+
+    fn deref(&self) -> &Audio {
+        &self.audio
+    }
+}
+
+impl DerefMut for AudioGuard {
+    fn deref_mut(&mut self) -> &mut Audio {
+        &mut self.audio
+    }
+}
+
+impl Drop for AudioGuard {
+    /// Stop the audio instance like `Audio::stop_nowait`.
+    ///
+    /// The stop command is written even if the audio instance has already finished, in
+    /// which case the repl.it audio playing program ignores it. Errors in writing the
+    /// command cannot be returned from `drop`, so they are logged as warnings through the
+    /// `log` crate instead.
+    fn drop(&mut self) {
+        if let Err(e) = self.audio.stop_nowait() {
+            log::warn!("Error in stopping audio source {} when its guard was dropped. ({})", self.audio.id, e);
+        }
+    }
+}
+
+impl PartialEq for Audio {
+    fn eq(&self, other: &Audio) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Audio {}
+
+impl Hash for Audio {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+pub(crate) fn update_source(backend: &Arc<dyn AudioBackend>, id: u64, update: &AudioUpdate) -> AudioResult<()> {
+    check_volume(update.volume)?;
+    check_pan(update.pan)?;
+
+    let serialized = object! {
+        ID: id,
+        Volume: update.volume,
+        Pan: update.pan,
+        Paused: update.paused,
+        DoesLoop: update.does_loop,
+        LoopCount: update.loop_count
+    };
+
+    if soft_mode_skip_update(id) {
+        return Ok(());
+    }
+
+    write_update(backend, id, serialized)
+}
+
+// set the volume while keeping the paused state and loop count from the current status
+fn set_source_volume(backend: &Arc<dyn AudioBackend>, id: u64, volume: f64) -> AudioResult<()> {
+    if soft_mode_skip_update(id) {
+        return Ok(());
+    }
+
+    let status = get_status_by_id(backend, id)?;
+    update_source(backend, id, &AudioUpdate { volume, ..update_from_status(&status)? })
+}
+
+/// Convert a volume in decibels to a linear volume, where `0.0` dB is `1.0`, `-6.0` dB is
+/// about `0.5`, and `f64::NEG_INFINITY` dB is `0.0`.
+pub fn db_to_linear(db: f64) -> f64 {
+    10f64.powf(db / 20.0)
+}
+
+/// Convert a linear volume to decibels, where `1.0` is `0.0` dB and `0.0` is
+/// `f64::NEG_INFINITY` dB.
+pub fn linear_to_db(volume: f64) -> f64 {
+    20.0 * volume.log10()
+}
+
+fn check_pan(pan: f64) -> AudioResult<()> {
+    if (-1.0..=1.0).contains(&pan) {
+        Ok(())
+    } else {
+        Err(AudioError::new(format!("The pan {} is not between -1 and 1.", pan)))
+    }
+}
+
+pub(crate) fn check_volume(volume: f64) -> AudioResult<()> {
+    if (0.0..=MAX_VOLUME).contains(&volume) {
+        Ok(())
+    } else {
+        Err(AudioError::new(format!("The volume {} is not between 0 and {}.", volume, MAX_VOLUME)))
+    }
+}
+
+// an update that keeps the audio source as it is in the status
+fn update_from_status(status: &json::JsonValue) -> AudioResult<AudioUpdate> {
+    let loop_count = status_field(status, "Loop", json::JsonValue::as_i64)?;
+
+    Ok(AudioUpdate {
+        volume: status_field(status, "Volume", json::JsonValue::as_f64)?,
+        // the status file may not have the pan
+        pan: status["Pan"].as_f64().unwrap_or(0.0),
+        paused: status_field(status, "Paused", json::JsonValue::as_bool)?,
+        does_loop: loop_count != 0,
+        loop_count
+    })
+}
+
+/// Custom result type for playing audio.
+pub type AudioResult<T> = Result<T, AudioError>;
+
+/// Custom error type for audio errors.
+///
+/// New variants may be added in the future, so matching on an `AudioError` needs a
+/// wildcard arm.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum AudioError {
+    /// Error in reading or writing a file, like the status file.
+    Io(io::Error),
+    /// Error in parsing JSON, like the contents of the status file.
+    JsonParse(json::Error),
+    /// The status file is empty, which happens briefly while the repl.it audio playing
+    /// program writes it, so reading it again usually works.
+    EmptyStatus,
+    /// The status file ends in the middle of its JSON, which happens briefly while the
+    /// repl.it audio playing program writes it, so reading it again usually works.
+    TruncatedStatus,
+    /// No audio source with the id was found in the status file. This usually means that
+    /// the audio instance has finished or was stopped.
+    SourceNotFound { id: u64 },
+    /// Timed out while waiting for the repl.it audio playing program.
+    Timeout(String),
+    /// A field in the status file is missing or has the wrong type, like when the status
+    /// file is read while it is being written.
+    InvalidStatusField { field: String, msg: String },
+    /// Audio is disabled in the repl, so the repl.it audio playing program does not play
+    /// anything.
+    Disabled,
+    /// The file at the path, which is written to or read by the repl.it audio playing
+    /// program, does not exist. This usually means that the program is not running on
+    /// repl.it (see `detect_environment` and `set_soft_mode`).
+    NotOnReplit { path: String },
+    /// An audio instance did not begin playing before the timeout, and the repl.it audio
+    /// playing program reports that nothing is playing, so it is probably not running or
+    /// not reading commands.
+    ///
+    /// If other audio sources are playing, then `Timeout` is returned instead.
+    NotRunning,
+    /// A name for a new audio instance is given more than once, or is already used by an
+    /// audio source (see `AudioBuilder::build_all_named`).
+    DuplicateName { name: String },
+    /// Any other error, like an invalid argument.
+    Other(String)
+}
+
+impl AudioError {
+    pub(crate) fn new(msg: String) -> AudioError {
+        AudioError::Other(msg)
+    }
+
+    /// Get whether the error is likely to go away when trying again, like when the status
+    /// file was read while it was being written (`EmptyStatus` and `TruncatedStatus`), or
+    /// when reading it was interrupted.
+    ///
+    /// Other errors, like malformed JSON that is not just cut off, are not retryable.
+    /// Reading the status file is already retried a few times before these errors are
+    /// returned.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AudioError::EmptyStatus | AudioError::TruncatedStatus => true,
+            AudioError::Io(e) => matches!(e.kind(), io::ErrorKind::Interrupted | io::ErrorKind::WouldBlock),
+            _ => false
+        }
+    }
+}
+
+impl fmt::Display for AudioError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioError::Io(e) => write!(f, "IO error. ({})", e),
+            AudioError::JsonParse(e) => write!(f, "Error in parsing JSON. ({})", e),
+            AudioError::EmptyStatus => write!(f, "The status file is empty."),
+            AudioError::TruncatedStatus => write!(f, "The status file is only partially written."),
+            AudioError::SourceNotFound { id } => write!(f, "No audio source found with id {}.", id),
+            AudioError::Disabled => write!(f, "Audio is disabled in this repl."),
+            AudioError::NotRunning => write!(f, "The repl.it audio playing program is not running."),
+            AudioError::DuplicateName { name } => {
+                write!(f, "The name {} is given more than once or is already used by an audio source.", name)
+            },
+            AudioError::NotOnReplit { path } => {
+                write!(f, "replit_audio requires the repl.it audio environment ({} not found).", path)
+            },
+            AudioError::Timeout(msg) | AudioError::InvalidStatusField { msg, .. } | AudioError::Other(msg) => {
+                write!(f, "{}", msg)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for AudioError {
+    fn from(e: io::Error) -> AudioError {
+        AudioError::Io(e)
+    }
+}
+
+impl From<json::Error> for AudioError {
+    fn from(e: json::Error) -> AudioError {
+        AudioError::JsonParse(e)
+    }
+}
+
+impl error::Error for AudioError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            AudioError::Io(e) => Some(e),
+            AudioError::JsonParse(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+/// Environment that the program is running in.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Environment {
+    Replit,
+    Other
+}
+
+/// Type of audio to play: audio file or tone.
+#[derive(Debug, PartialEq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AudioType {
+    File { file: FileType, path: String },
+    Tone { tone: ToneType, pitch: f64, duration: f64 }
+}
+
+impl AudioType {
+    /// Create an `AudioType::File` from a file type and a path.
+    ///
+    /// The repl.it audio playing program requires UTF-8 paths, so this returns an error
+    /// if the path is not valid UTF-8 instead of converting it lossily.
+    pub fn file<P: AsRef<Path>>(file: FileType, path: P) -> AudioResult<AudioType> {
+        match path.as_ref().to_str() {
+            Some(p) => Ok(AudioType::File { file, path: p.to_owned() }),
+            None => Err(AudioError::new(format!("The path {} is not valid UTF-8.", path.as_ref().display())))
+        }
+    }
+
+    /// Create an `AudioType::File` from a path, with the file type inferred from the
+    /// extension of the path (see `FileType::from_path`).
+    ///
+    /// The path must be valid UTF-8, like in `AudioType::file`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> AudioResult<AudioType> {
+        AudioType::file(FileType::from_path(&path)?, path)
+    }
+
+    /// Create an `AudioType::Tone` whose pitch is the `base` pitch multiplied by the ratio
+    /// `numerator / denominator`, like `3 / 2` for a just perfect fifth.
+    ///
+    /// An error is returned if the `denominator` is zero or if the resulting pitch is not
+    /// between `20.0` and `20000.0` Hz, which is the range of human hearing.
+    pub fn ratio(base: f64, numerator: u32, denominator: u32, tone: ToneType, duration: f64) -> AudioResult<AudioType> {
+        if denominator == 0 {
+            return Err(AudioError::new(format!("The ratio {}/{} has a zero denominator.", numerator, denominator)));
+        }
+
+        let pitch = base * numerator as f64 / denominator as f64;
+
+        if !(MIN_PITCH..=MAX_PITCH).contains(&pitch) {
+            return Err(AudioError::new(format!("The pitch {} is not between {} and {} Hz.", pitch, MIN_PITCH, MAX_PITCH)));
+        }
+
+        Ok(AudioType::Tone { tone, pitch, duration })
+    }
+
+    /// Get whether both audio types are files of the same format or tones of the same
+    /// tone type, ignoring the path, pitch, and duration.
+    pub fn same_kind(&self, other: &AudioType) -> bool {
+        match (self, other) {
+            (AudioType::File { file: a, .. }, AudioType::File { file: b, .. }) => a == b,
+            (AudioType::Tone { tone: a, .. }, AudioType::Tone { tone: b, .. }) => a == b,
+            _ => false
+        }
+    }
+
+    /// Get whether both audio types are tones of the same tone type whose pitches and
+    /// durations are approximately equal, or files that are equal.
+    ///
+    /// The `tolerance` is relative, since pitches in Hz and durations in seconds have very
+    /// different scales. Two values are approximately equal if they differ by at most
+    /// `tolerance` times the larger of their absolute values, so a `tolerance` of `0.01`
+    /// allows a difference of 1% in both the pitch and the duration.
+    pub fn approx_eq(&self, other: &AudioType, tolerance: f64) -> bool {
+        let close = |a: f64, b: f64| (a - b).abs() <= tolerance * a.abs().max(b.abs());
+
+        match (self, other) {
+            (AudioType::Tone { tone: a, pitch: a_pitch, duration: a_duration },
+                AudioType::Tone { tone: b, pitch: b_pitch, duration: b_duration }) => {
+                a == b && close(*a_pitch, *b_pitch) && close(*a_duration, *b_duration)
+            },
+            _ => self == other
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            AudioType::File { file, .. } => file.as_str(),
+            AudioType::Tone { .. } => "tone"
+        }
+    }
+}
+
+impl fmt::Display for AudioType {
+    /// Format the audio type as a short summary, like `tone(square, 440Hz, 2s)` or
+    /// `file(wav, audio.wav)`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AudioType::File { file, path } => write!(f, "file({}, {})", file, path),
+            AudioType::Tone { tone, pitch, duration } => write!(f, "tone({}, {}Hz, {}s)", tone, pitch, duration)
+        }
+    }
+}
+
+/// Supported audio file formats.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FileType {
+    Wav,
+    Aiff,
+    Mp3,
+    Ogg,
+    Flac
+}
+
+impl FileType {
+    /// Infer the file type from the extension of a path, ignoring case.
+    ///
+    /// The extensions `wav`, `aiff`, `aif`, `mp3`, `ogg`, and `flac` are recognized. If the
+    /// extension is missing or not recognized, then the default file type set with
+    /// `set_default_file_type` is used, or an error is returned if there is no default.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> AudioResult<FileType> {
+        file_type_from_path(path.as_ref(), *DEFAULT_FILE_TYPE.lock().unwrap())
+    }
+
+    fn from_type_str(type_str: &str) -> Option<FileType> {
+        match type_str {
+            "wav" => Some(FileType::Wav),
+            "aiff" => Some(FileType::Aiff),
+            "mp3" => Some(FileType::Mp3),
+            "ogg" => Some(FileType::Ogg),
+            "flac" => Some(FileType::Flac),
+            _ => None
+        }
+    }
+
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            FileType::Wav => "wav",
+            FileType::Aiff => "aiff",
+            FileType::Mp3 => "mp3",
+            FileType::Ogg => "ogg",
+            FileType::Flac => "flac"
+        }
+    }
+}
+
+impl fmt::Display for FileType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for FileType {
+    type Err = AudioError;
+
+    /// Parse a file type from its name, like `wav` or `mp3`, ignoring case.
+    fn from_str(s: &str) -> AudioResult<FileType> {
+        FileType::from_type_str(&s.to_ascii_lowercase())
+            .ok_or_else(|| AudioError::new(format!("Unknown file type {}.", s)))
+    }
+}
+
+/// Supported tone types.
+#[derive(Debug, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ToneType {
+    Sine = 0,
+    Triangle = 1,
+    Saw = 2,
+    Square = 3
+}
+
+impl ToneType {
+    fn from_u8(wave_type: u8) -> Option<ToneType> {
+        match wave_type {
+            0 => Some(ToneType::Sine),
+            1 => Some(ToneType::Triangle),
+            2 => Some(ToneType::Saw),
+            3 => Some(ToneType::Square),
+            _ => None
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            ToneType::Sine => "sine",
+            ToneType::Triangle => "triangle",
+            ToneType::Saw => "saw",
+            ToneType::Square => "square"
+        }
+    }
+}
+
+impl fmt::Display for ToneType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for ToneType {
+    type Err = AudioError;
+
+    /// Parse a tone type from its name, like `sine` or `square`, ignoring case.
+    fn from_str(s: &str) -> AudioResult<ToneType> {
+        [ToneType::Sine, ToneType::Triangle, ToneType::Saw, ToneType::Square].iter()
+            .copied()
+            .find(|t| t.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| AudioError::new(format!("Unknown tone type {}.", s)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::{NaiveDate, Timelike};
+
+    use crate::backend::{playing_status, single_read_status, source_status, status_with, MockBackend};
+    use crate::clock::TEST_LOCK as CLOCK_LOCK;
+
+    #[test]
+    fn test_audio_status_extra() {
+        let status = json::parse(r#"{
+            "Name": "rust_audio_0", "Type": "tone", "ID": 1,
+            "Volume": 0.5, "Duration": 2000, "Remaining": 500, "Paused": false, "Loop": 0,
+            "StartTime": "2020-08-01T12:00:00.000000000Z", "EndTime": "2020-08-01T12:00:02.000000000Z",
+            "NewField": [1, 2, 3]
+        }"#).unwrap();
+
+        let status = AudioStatus::from_json(status).unwrap();
+        assert_eq!(status.name, "rust_audio_0");
+        assert_eq!(status.remaining, 500);
+        assert_eq!(status.extra.len(), 2);
+        assert_eq!(status.extra["Type"], "tone");
+        assert_eq!(status.extra["NewField"], json::array![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_audio_status_type() {
+        let status = |fields: &str| AudioStatus::from_json(json::parse(&format!(r#"{{
+            "Name": "rust_audio_0", "ID": 1,
+            "Volume": 0.5, "Duration": 2000, "Remaining": 500, "Paused": false, "Loop": 0,
+            "StartTime": "2020-08-01T12:00:00Z", "EndTime": "2020-08-01T12:00:02Z",
+            {}
+        }}"#, fields)).unwrap()).unwrap();
+
+        let tone = status(r#""Type": "tone", "Args": { "WaveType": 1, "Pitch": 220.0, "Seconds": 2.0 }"#);
+        assert_eq!(tone.audio_type().unwrap(), AudioType::Tone { tone: ToneType::Triangle, pitch: 220.0, duration: 2.0 });
+
+        let file = status(r#""Type": "mp3", "Args": { "Path": "music.mp3" }"#);
+        assert_eq!(file.audio_type().unwrap(), AudioType::File { file: FileType::Mp3, path: "music.mp3".to_owned() });
+
+        let unknown = status(r#""Type": "midi", "Args": { "Path": "music.mid" }"#);
+        assert!(matches!(unknown.audio_type(), Err(AudioError::InvalidStatusField { ref field, .. }) if field == "Type"));
+        assert!(status(r#""Type": "tone""#).audio_type().is_err());
+        assert!(status(r#""Args": {}"#).audio_type().is_err());
+    }
+
+    #[test]
+    fn test_audio_status_fields() {
+        let status = json::parse(r#"{
+            "Name": "rust_audio_0", "Type": "tone", "ID": 3,
+            "Volume": 0.5, "Duration": 2000, "Remaining": 500, "Paused": true, "Loop": -1,
+            "StartTime": "2020-08-01T12:00:00.000000000Z", "EndTime": "2020-08-01T12:00:02.000000000Z"
+        }"#).unwrap();
+
+        let status = AudioStatus::from_json(status).unwrap();
+        let start = NaiveDate::from_ymd_opt(2020, 8, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        assert_eq!(status.id, 3);
+        assert_eq!(status.volume, 0.5);
+        assert_eq!(status.duration, 2000);
+        assert!(status.paused);
+        assert_eq!(status.loop_count, -1);
+        assert_eq!(status.start_time, start);
+        assert_eq!(status.end_time, start + chrono::Duration::seconds(2));
+        assert_eq!(status.start_time_utc(), DateTime::parse_from_rfc3339("2020-08-01T12:00:00Z").unwrap());
+        assert_eq!(status.end_time_utc().to_rfc3339(), "2020-08-01T12:00:02+00:00");
+    }
+
+    #[test]
+    fn test_sources_from_status() {
+        let status = json::parse(r#"{
+            "Sources": [
+                { "Name": "b", "ID": 2, "Volume": 1.0, "Duration": 1000, "Remaining": 500, "Paused": false, "Loop": 0,
+                    "StartTime": "2020-08-01T12:00:00Z", "EndTime": "2020-08-01T12:00:01Z" },
+                { "Name": "a", "ID": 1, "Volume": 1.0, "Duration": 1000, "Remaining": 500, "Paused": false, "Loop": 0,
+                    "StartTime": "2020-08-01T12:00:00Z", "EndTime": "2020-08-01T12:00:01Z" }
+            ],
+            "Running": true, "Disabled": false
+        }"#).unwrap();
+
+        let names = sources_from_status(status).unwrap().into_iter().map(|s| s.name).collect::<Vec<_>>();
+        assert_eq!(names, ["b", "a"]);
+
+        let empty = json::parse(r#"{ "Sources": [], "Running": false, "Disabled": false }"#).unwrap();
+        assert!(sources_from_status(empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_custom_backend() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_backend", 5, 0.5)])));
+
+        let mut audio = AudioBuilder::new(&AudioType::Tone { tone: ToneType::Sine, pitch: 440.0, duration: 1.0 })
+            .name("rust_audio_test_backend")
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .build()
+            .unwrap();
+
+        assert_eq!(audio.get_id(), 5);
+        assert_eq!(audio.get_volume().unwrap(), 0.5);
+        audio.stop().unwrap();
+
+        let commands = backend.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0]["Name"], "rust_audio_test_backend");
+        assert_eq!(commands[1]["Stop"], true);
+    }
+
+    #[test]
+    fn test_play_for() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_play_for", 8, 1.0)])));
+        let builder = AudioBuilder::file(FileType::Wav, "audio.wav")
+            .name("rust_audio_test_play_for")
+            .play_for(Duration::from_millis(50))
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>);
+        let stop_count = || backend.commands().iter().filter(|c| c["Stop"] == true).count();
+
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let start = Instant::now();
+        let audio = builder.clone().build().unwrap();
+        assert_eq!(stop_count(), 0);
+
+        while stop_count() == 0 && start.elapsed() < Duration::from_secs(1) {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        assert_eq!(stop_count(), 1);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(backend.commands().last().unwrap()["ID"], audio.get_id());
+
+        // the stop is cancelled with the other background tasks
+        builder.build().unwrap().cancel_background_tasks();
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(stop_count(), 1);
+    }
+
+    #[test]
+    fn test_play_for_busy_workers() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_play_for_busy", 8, 1.0)])));
+        let builder = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .name("rust_audio_test_play_for_busy")
+            .play_for(Duration::from_millis(20))
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>);
+
+        // more tasks than worker threads, which keep every worker thread busy
+        let (release, released) = mpsc::channel::<()>();
+        let released = Arc::new(Mutex::new(released));
+
+        for _ in 0..16 {
+            let released = Arc::clone(&released);
+            worker::execute(move || { let _ = released.lock().unwrap().recv_timeout(Duration::from_secs(5)); });
+        }
+
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let start = Instant::now();
+        builder.build().unwrap();
+
+        while backend.commands().len() < 2 && start.elapsed() < Duration::from_secs(1) {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        // the stop does not wait for a worker thread
+        let stopped = backend.commands().iter().any(|c| c["Stop"] == true);
+        drop(release);
+        assert!(stopped);
+    }
+
+    #[test]
+    fn test_await_start_twice() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_await", 3, 1.0)])));
+        let pending = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .name("rust_audio_test_await")
+            .play_for(Duration::from_millis(20))
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .fire()
+            .unwrap();
+
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        assert_eq!(pending.await_start(Duration::ZERO).unwrap().get_id(), 3);
+        assert_eq!(pending.await_start(Duration::ZERO).unwrap().get_id(), 3);
+        thread::sleep(Duration::from_millis(150));
+
+        // the audio instance is only stopped once
+        let stops = backend.commands().iter().filter(|c| c["Stop"] == true).count();
+        assert_eq!(stops, 1);
+    }
+
+    #[test]
+    fn test_build_async() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_build_async", 6, 1.0)])));
+        let builder = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .timeout(Duration::from_millis(200));
+
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let handle = builder.clone().name("rust_audio_test_build_async").build_async();
+        assert_eq!(handle.name(), "rust_audio_test_build_async");
+        assert_eq!(handle.join().unwrap().get_id(), 6);
+
+        // building with a generated name times out, but only in the background task
+        let start = Instant::now();
+        let handle = builder.build_async();
+        assert!(start.elapsed() < Duration::from_millis(100));
+        assert!(!handle.is_finished());
+        assert!(matches!(handle.join(), Err(AudioError::Timeout(_))));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // the result is kept once is_finished sees it
+        let handle = builder.clone().name("rust_audio_test_build_async").build_async();
+        while !handle.is_finished() {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(handle.join().unwrap().get_id(), 6);
+
+        // errors are the same as with build
+        let handle = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0).volume(2.0).build_async();
+        assert!(handle.join().is_err());
+        assert_eq!(backend.commands().len(), 3);
+    }
+
+    #[test]
+    fn test_build_all_named_duplicate() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_taken", 2, 1.0)])));
+        let builder = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>);
+
+        let err = builder.build_all_named(&["rust_audio_test_free", "rust_audio_test_taken"]).err().unwrap();
+        assert!(matches!(&err, AudioError::DuplicateName { name } if name == "rust_audio_test_taken"));
+        assert_eq!(err.to_string(), "The name rust_audio_test_taken is given more than once or is already used by an audio source.");
+        assert!(matches!(builder.build_all_named(&["a", "b", "a"]), Err(AudioError::DuplicateName { name }) if name == "a"));
+
+        // nothing is played if a name is a duplicate
+        assert!(backend.commands().is_empty());
+    }
+
+    #[test]
+    fn test_serialize_build() {
+        let tone = AudioType::Tone { tone: ToneType::Triangle, pitch: 440.0, duration: 2.5 };
+        assert_eq!(
+            serialize_build("rust_audio_test_tone", &tone, 0.5, -0.25, true, 3).dump(),
+            r#"{"Name":"rust_audio_test_tone","Type":"tone","Volume":0.5,"Pan":-0.25,"DoesLoop":true,"LoopCount":3,"Args":{"WaveType":1,"Pitch":440,"Seconds":2.5}}"#
+        );
+
+        let file = AudioType::File { file: FileType::Mp3, path: "music/song.mp3".to_owned() };
+        let serialized = serialize_build("rust_audio_test_file", &file, 1.0, 0.0, false, -1);
+        assert_eq!(serialized["Type"], "mp3");
+        assert_eq!(serialized["DoesLoop"], false);
+        assert_eq!(serialized["LoopCount"], -1);
+        assert_eq!(serialized["Args"], object! { Path: "music/song.mp3" });
+        assert_eq!(serialized.len(), 7);
+    }
+
+    #[test]
+    fn test_audio_eq_and_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::collections::HashSet;
+
+        let hash = |audio: &Audio| {
+            let mut hasher = DefaultHasher::new();
+            audio.hash(&mut hasher);
+            hasher.finish()
+        };
+
+        let builder = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0);
+        let first = builder.audio_with_id(3, "rust_audio_test_eq_a");
+        let same = builder.clone().volume(0.5).audio_with_id(3, "rust_audio_test_eq_b");
+        let other = builder.audio_with_id(4, "rust_audio_test_eq_a");
+
+        assert!(first == same);
+        assert_eq!(hash(&first), hash(&same));
+        assert!(first != other);
+
+        let set = vec![first.clone(), same, other, first.clone()].into_iter().collect::<HashSet<_>>();
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&first));
+    }
+
+    #[test]
+    fn test_clone_builder() {
+        let base = AudioBuilder::tone(ToneType::Square, 440.0, 0.5)
+            .volume(0.25)
+            .does_loop(true)
+            .loop_count(2);
+        let louder = base.clone().volume(0.75);
+
+        assert_eq!(louder.volume, 0.75);
+        assert_eq!(louder.loop_count, 2);
+        assert_eq!(louder.audio_type, AudioType::Tone { tone: ToneType::Square, pitch: 440.0, duration: 0.5 });
+        assert_eq!(base.volume, 0.25);
+        assert!(Arc::ptr_eq(&base.backend, &louder.backend));
+    }
+
+    #[test]
+    fn test_db_conversion() {
+        assert_eq!(db_to_linear(0.0), 1.0);
+        assert!((db_to_linear(-6.0) - 0.501).abs() < 0.001);
+        assert!((db_to_linear(-20.0) - 0.1).abs() < 1e-12);
+        assert_eq!(db_to_linear(f64::NEG_INFINITY), 0.0);
+
+        assert_eq!(linear_to_db(1.0), 0.0);
+        assert!((linear_to_db(0.1) + 20.0).abs() < 1e-12);
+        assert_eq!(linear_to_db(0.0), f64::NEG_INFINITY);
+        assert!((linear_to_db(db_to_linear(-12.5)) + 12.5).abs() < 1e-12);
+
+        let builder = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0);
+        assert!((builder.clone().volume_db(-20.0).volume - 0.1).abs() < 1e-12);
+        assert_eq!(builder.volume_db(6.0).volume, 1.0);
+    }
+
+    #[test]
+    fn test_mute() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_mute", 12, 0.5)])));
+        let mut audio = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .name("rust_audio_test_mute")
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .build()
+            .unwrap();
+
+        audio.mute().unwrap();
+        assert!(audio.is_muted());
+        // muting again does not forget the volume
+        audio.mute().unwrap();
+        audio.unmute().unwrap();
+        assert!(!audio.is_muted());
+        // unmuting an audio instance that is not muted sets the full volume
+        audio.unmute().unwrap();
+        audio.set_volume_db(-20.0).unwrap();
+        audio.set_volume_db(3.0).unwrap();
+
+        let volumes = backend.commands().iter()
+            .skip(1)
+            .map(|c| c["Volume"].as_f64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(volumes.len(), 5);
+        assert_eq!(volumes[..3], [0.0, 0.5, 1.0]);
+        assert!((volumes[3] - 0.1).abs() < 1e-12);
+        assert_eq!(volumes[4], 1.0);
+    }
+
+    #[test]
+    fn test_refresh() {
+        let mut source = source_status("rust_audio_test_refresh", 7, 0.5);
+        source["Paused"] = true.into();
+        source["Loop"] = 2.into();
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source])));
+        let mut audio = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .name("rust_audio_test_refresh")
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .build()
+            .unwrap();
+
+        assert!(audio.cached_status().is_none());
+        assert_eq!(audio.cached_volume(), None);
+
+        audio.refresh().unwrap();
+        assert_eq!(audio.cached_volume(), Some(0.5));
+        assert_eq!(audio.cached_remaining(), Some(500));
+        assert_eq!(audio.cached_paused(), Some(true));
+        assert_eq!(audio.cached_loop_count(), Some(2));
+        assert_eq!(audio.cached_status().unwrap().name, "rust_audio_test_refresh");
+
+        // the cached status is kept when the audio instance finishes
+        audio.backend = Arc::new(MockBackend::new());
+        assert!(audio.refresh().is_err());
+        assert_eq!(audio.cached_volume(), Some(0.5));
+    }
+
+    #[test]
+    fn test_missing_status_field() {
+        // a status file that was read while it was being written
+        let mut source = source_status("rust_audio_test_missing", 6, 1.0);
+        source.remove("Volume");
+        source["Paused"] = "no".into();
+        let status = status_with(vec![source]);
+        let backend = Arc::new(MockBackend::with_status(&status));
+
+        let mut audio = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .name("rust_audio_test_missing")
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .build()
+            .unwrap();
+
+        let missing = |r: AudioResult<_>, expected: &str| match r {
+            Err(AudioError::InvalidStatusField { field, .. }) => assert_eq!(field, expected),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(()) => panic!("expected an error")
+        };
+        missing(audio.get_volume().map(|_| ()), "Volume");
+        missing(audio.status().map(|_| ()), "Volume");
+        missing(audio.get_volume_linear_and_db().map(|_| ()), "Volume");
+        missing(audio.pause(), "Volume");
+        assert_eq!(audio.get_volume().unwrap_err().to_string(), "Missing Volume field.");
+
+        let status = json::parse(&status).unwrap();
+        let source = &status["Sources"][0];
+        assert_eq!(status_field(source, "Paused", json::JsonValue::as_bool).unwrap_err().to_string(), "Invalid Paused field no.");
+        assert!(snapshot_from_status(Arc::clone(&backend) as Arc<dyn AudioBackend>, &status).sources.is_empty());
+
+        // only the command that played the audio instance was written
+        assert_eq!(backend.commands().len(), 1);
+    }
+
+    #[test]
+    fn test_generated_names_differ_across_processes() {
+        // two programs that have played the same number of audio instances
+        let this = generated_name(&PROCESS_TAG, 3);
+        let other = generated_name("1_17f0a1b2c3d", 3);
+        assert_ne!(this, other);
+        assert!(this.starts_with(&format!("{}{}_", DEFAULT_NAME_PREFIX, process::id())));
+        assert!(this.ends_with("_3"));
+        assert!(peek_next_name().starts_with(&format!("{}{}_", DEFAULT_NAME_PREFIX, *PROCESS_TAG)));
+    }
+
+    #[test]
+    fn test_clone_audio() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_clone", 8, 0.5)])));
+
+        let audio = AudioBuilder::new(&AudioType::Tone { tone: ToneType::Sine, pitch: 440.0, duration: 1.0 })
+            .name("rust_audio_test_clone")
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .build()
+            .unwrap();
+        let clone = audio.clone();
+
+        assert_eq!(clone.get_id(), audio.get_id());
+        assert_eq!(clone.name(), "rust_audio_test_clone");
+        assert_eq!(clone.get_name().unwrap(), audio.get_name().unwrap());
+        // cloning does not play the audio instance again
+        assert_eq!(backend.commands().len(), 1);
+    }
+
+    #[test]
+    fn test_guard_stops_on_drop() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_guard", 9, 0.5)])));
+
+        {
+            let guard = AudioBuilder::new(&AudioType::Tone { tone: ToneType::Sine, pitch: 440.0, duration: 1.0 })
+                .name("rust_audio_test_guard")
+                .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+                .build()
+                .unwrap()
+                .into_guard();
+
+            assert_eq!(guard.get_id(), 9);
+            assert_eq!(backend.commands().len(), 1);
+        }
+
+        let commands = backend.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[1]["ID"], 9);
+        assert_eq!(commands[1]["Stop"], true);
+    }
+
+    #[test]
+    fn test_error_variants() {
+        let backend: Arc<dyn AudioBackend> = Arc::new(MockBackend::with_status(&status_with(Vec::new())));
+        let err = get_status_by_id(&backend, 3).unwrap_err();
+        assert!(matches!(err, AudioError::SourceNotFound { id: 3 }));
+        assert_eq!(err.to_string(), "No audio source found with id 3.");
+
+        let backend: Arc<dyn AudioBackend> = Arc::new(MockBackend::with_status(r#"{ "Sources": ] }"#));
+        assert!(matches!(get_status_by_id(&backend, 3).unwrap_err(), AudioError::JsonParse(_)));
+
+        match parse_time("", "end").unwrap_err() {
+            AudioError::InvalidStatusField { field, .. } => assert_eq!(field, "EndTime"),
+            e => panic!("unexpected error {:?}", e)
+        }
+
+        let err = AudioError::from(io::Error::new(io::ErrorKind::NotFound, "missing"));
+        assert!(matches!(err, AudioError::Io(_)));
+        assert_eq!(err.to_string(), "IO error. (missing)");
+    }
+
+    #[test]
+    fn test_partial_status() {
+        for empty in &["", "  \n"] {
+            let err = parse_status_str(empty).unwrap_err();
+            assert!(matches!(err, AudioError::EmptyStatus));
+            assert!(err.is_retryable());
+        }
+
+        let full = r#"{ "Sources": [{ "Name": "rust_audio_0", "ID": 1 }], "Running": true, "Disabled": false }"#;
+        assert!(parse_status_str(full).is_ok());
+
+        // cut off at every point in the middle
+        for end in 1..full.len() {
+            let err = parse_status_str(&full[..end]).unwrap_err();
+            assert!(matches!(err, AudioError::TruncatedStatus), "{}: {:?}", &full[..end], err);
+            assert!(err.is_retryable());
+        }
+
+        let err = parse_status_str(r#"{ "Sources": ] }"#).unwrap_err();
+        assert!(matches!(err, AudioError::JsonParse(_)));
+        assert!(!err.is_retryable());
+        assert!(!AudioError::SourceNotFound { id: 1 }.is_retryable());
+    }
+
+    #[test]
+    fn test_with_retry() {
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let clock = clock::MockClock::install();
+
+        let mut calls = 0;
+        let result = with_retry(3, || {
+            calls += 1;
+            if calls < 3 { Err(AudioError::TruncatedStatus) } else { Ok(calls) }
+        });
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(clock.elapsed(), STATUS_RETRY_DELAY * 3);
+
+        // gives up after the last attempt
+        let mut calls = 0;
+        let result: AudioResult<()> = with_retry(3, || { calls += 1; Err(AudioError::EmptyStatus) });
+        assert!(matches!(result, Err(AudioError::EmptyStatus)));
+        assert_eq!(calls, 3);
+
+        // errors that are not transient are returned right away
+        let mut calls = 0;
+        let result: AudioResult<()> = with_retry(3, || { calls += 1; Err(AudioError::SourceNotFound { id: 1 }) });
+        clock::MockClock::uninstall();
+        assert!(matches!(result, Err(AudioError::SourceNotFound { id: 1 })));
+        assert_eq!(calls, 1);
+        assert_eq!(clock.elapsed(), STATUS_RETRY_DELAY * 6);
+    }
+
+    #[test]
+    fn test_error_source() {
+        use std::error::Error;
+
+        let err = AudioError::from(io::Error::new(io::ErrorKind::NotFound, "missing"));
+        let source = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.kind(), io::ErrorKind::NotFound);
+
+        let err = AudioError::from(json::parse("{").unwrap_err());
+        assert!(err.source().unwrap().downcast_ref::<json::Error>().is_some());
+
+        assert!(AudioError::SourceNotFound { id: 3 }.source().is_none());
+    }
+
+    #[test]
+    fn test_build_timeout() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(Vec::new())));
+        let builder = AudioBuilder::new(&AudioType::Tone { tone: ToneType::Sine, pitch: 440.0, duration: 1.0 })
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>);
+        assert_eq!(builder.timeout, DEFAULT_BUILD_TIMEOUT);
+
+        let builder = builder.timeout(Duration::from_millis(50));
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        assert_eq!(builder.with_type(&AudioType::Tone { tone: ToneType::Sine, pitch: 220.0, duration: 1.0 }).timeout, Duration::from_millis(50));
+
+        let start = Instant::now();
+        assert!(matches!(builder.build(), Err(AudioError::Timeout(_))));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(backend.commands().len(), 1);
+    }
+
+    #[test]
+    fn test_start_timeout_errors() {
+        let status = |s: &str| json::parse(s).unwrap();
+
+        let disabled = status(r#"{ "Sources": [], "Running": false, "Disabled": true }"#);
+        assert!(matches!(start_timeout_error(Some(&disabled), "a"), AudioError::Disabled));
+
+        let idle = status(r#"{ "Sources": [], "Running": false, "Disabled": false }"#);
+        let err = start_timeout_error(Some(&idle), "a");
+        assert!(matches!(err, AudioError::NotRunning));
+        assert_eq!(err.to_string(), "The repl.it audio playing program is not running.");
+
+        // other audio sources are playing, so the audio playing program is alive
+        let playing = status(r#"{ "Sources": [{ "Name": "b", "ID": 1 }], "Running": true, "Disabled": false }"#);
+        assert!(matches!(start_timeout_error(Some(&playing), "a"), AudioError::Timeout(_)));
+        assert!(matches!(start_timeout_error(None, "a"), AudioError::Timeout(_)));
+
+        let backend = Arc::new(MockBackend::with_status(&idle.dump()));
+        let result = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .timeout(Duration::ZERO)
+            .backend(backend as Arc<dyn AudioBackend>)
+            .build();
+        assert!(matches!(result, Err(AudioError::NotRunning)));
+    }
+
+    #[test]
+    fn test_fade_to() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_fade", 10, 0.5)])));
+        let mut audio = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .audio_with_id(10, "rust_audio_test_fade");
+
+        assert!(audio.fade_to(1.5, Duration::from_secs(1)).is_err());
+
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let clock = clock::MockClock::install();
+        let result = audio.fade_to_with_steps(0.0, Duration::from_secs(1), 4);
+        clock::MockClock::uninstall();
+        result.unwrap();
+
+        assert_eq!(clock.elapsed(), Duration::from_secs(1));
+        let volumes = backend.commands().iter()
+            .map(|c| c["Volume"].as_f64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(volumes, vec![0.375, 0.25, 0.125, 0.0]);
+    }
+
+    #[test]
+    fn test_fade_out_and_stop() {
+        let playing = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_fade_out", 11, 0.5)])));
+        let finished = Arc::new(MockBackend::new());
+        let audio = |backend: &Arc<MockBackend>| AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .backend(Arc::clone(backend) as Arc<dyn AudioBackend>)
+            .audio_with_id(11, "rust_audio_test_fade_out");
+
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let clock = clock::MockClock::install();
+        let result = audio(&playing).fade_out_and_stop(Duration::from_millis(200));
+        // the audio instance already finished, so there is nothing to fade or stop
+        let finished_result = audio(&finished).fade_out_and_stop(Duration::from_millis(200));
+        clock::MockClock::uninstall();
+        result.unwrap();
+        finished_result.unwrap();
+
+        assert!(clock.elapsed() >= Duration::from_millis(200));
+        let commands = playing.commands();
+        assert_eq!(commands.len(), 5);
+        assert_eq!(commands[3]["Volume"], 0.0);
+        assert_eq!(commands[4]["Stop"], true);
+        assert!(finished.commands().is_empty());
+    }
+
+    #[test]
+    fn test_play_sequence() {
+        let backend = Arc::new(MockBackend::new());
+        // tones with a pitch of 660 Hz never begin playing
+        backend.set_status_fn(single_read_status(|c| c["Args"]["Pitch"] != 660.0));
+        let tone = |pitch| AudioType::Tone { tone: ToneType::Sine, pitch, duration: 1.0 };
+
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let clock = clock::MockClock::install();
+        let backend_dyn = Arc::clone(&backend) as Arc<dyn AudioBackend>;
+        let played = play_sequence_on(&backend_dyn, &[tone(440.0), tone(550.0)], Duration::from_secs(60));
+        let played_elapsed = clock.elapsed();
+        let failed = play_sequence_on(&backend_dyn, &[tone(660.0), tone(770.0)], Duration::from_secs(60));
+        clock::MockClock::uninstall();
+
+        // there is a single gap, and none after the last audio instance
+        played.unwrap();
+        assert!(played_elapsed >= Duration::from_secs(60) && played_elapsed < Duration::from_secs(120));
+
+        // the first audio instance never begins playing, so the second one is not played
+        assert!(matches!(failed, Err(AudioError::Timeout(_))));
+        let pitches = backend.commands().iter()
+            .map(|c| c["Args"]["Pitch"].as_f64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(pitches, vec![440.0, 550.0, 660.0]);
+    }
+
+    #[test]
+    fn test_build_when_disabled() {
+        let disabled = Arc::new(MockBackend::with_status(r#"{ "Sources": [], "Running": false, "Disabled": true }"#));
+        let unreadable = Arc::new(MockBackend::with_status(""));
+        let builder = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0);
+
+        // reading the empty status is retried, which sleeps on the clock
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let start = Instant::now();
+        let result = builder.clone()
+            .backend(Arc::clone(&disabled) as Arc<dyn AudioBackend>)
+            .build();
+        assert!(matches!(result, Err(AudioError::Disabled)));
+        assert!(matches!(builder.backend(Arc::clone(&unreadable) as Arc<dyn AudioBackend>).fire(), Err(AudioError::EmptyStatus)));
+
+        // neither waits for the timeout
+        assert!(start.elapsed() < DEFAULT_BUILD_TIMEOUT);
+        assert!(disabled.commands().is_empty());
+        assert!(unreadable.commands().is_empty());
+    }
+
+    #[test]
+    fn test_pan() {
+        let mut source = source_status("rust_audio_test_pan", 13, 1.0);
+        source["Pan"] = (-0.5).into();
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source])));
+        let builder = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .name("rust_audio_test_pan")
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>);
+
+        assert!(builder.clone().pan(1.5).build().is_err());
+        let mut audio = builder.pan(-0.5).build().unwrap();
+        assert!(audio.update(&AudioUpdate { pan: -2.0, ..Default::default() }).is_err());
+        // the pan in the status is kept
+        audio.set_volume(0.5).unwrap();
+
+        let commands = backend.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0]["Pan"], -0.5);
+        assert_eq!(commands[1]["Pan"], -0.5);
+    }
+
+    #[test]
+    fn test_invalid_volume_not_written() {
+        let backend = Arc::new(MockBackend::new());
+        let builder = AudioBuilder::new(&AudioType::Tone { tone: ToneType::Sine, pitch: 440.0, duration: 1.0 })
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>);
+
+        assert!(builder.clone().volume(-0.5).build().is_err());
+        assert!(builder.clone().volume(1.5).fire().is_err());
+
+        let mut audio = builder.audio_with_id(1, "rust_audio_test_volume");
+        assert!(audio.update(&AudioUpdate { volume: 2.0, ..Default::default() }).is_err());
+        audio.update(&AudioUpdate { volume: 1.0, ..Default::default() }).unwrap();
+        audio.update(&AudioUpdate { volume: 0.0, ..Default::default() }).unwrap();
+
+        // only the valid updates were written
+        assert_eq!(backend.commands().len(), 2);
+    }
+
+    #[test]
+    fn test_lock_after_wait() {
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let start = Instant::now();
+        let limiter = Arc::new(Mutex::new(WriteLimiter {
+            min_interval: Duration::from_millis(200),
+            last_write: Some(start),
+            pending: HashMap::new()
+        }));
+        let waiting = {
+            let limiter = Arc::clone(&limiter);
+            thread::spawn(move || {
+                let mut guard = lock_after_wait(&limiter);
+                guard.last_write = Some(Instant::now());
+            })
+        };
+
+        // the lock is free while the other thread waits
+        thread::sleep(Duration::from_millis(20));
+        let guard = limiter.lock().unwrap();
+        assert!(start.elapsed() < Duration::from_millis(150));
+        drop(guard);
+
+        waiting.join().unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert!(limiter.lock().unwrap().last_write.unwrap() >= start + Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_write_limiter_wait_time() {
+        let now = Instant::now();
+        let mut limiter = WriteLimiter { min_interval: Duration::ZERO, last_write: None, pending: HashMap::new() };
+        assert_eq!(limiter.wait_time(now), Duration::ZERO);
+
+        limiter.last_write = Some(now);
+        assert_eq!(limiter.wait_time(now), Duration::ZERO);
+
+        limiter.min_interval = Duration::from_secs(60);
+        assert_eq!(limiter.wait_time(now), Duration::from_secs(60));
+        assert_eq!(limiter.wait_time(now + Duration::from_secs(15)), Duration::from_secs(45));
+        assert_eq!(limiter.wait_time(now + Duration::from_secs(61)), Duration::ZERO);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde_round_trip() {
+        let tone = AudioType::Tone { tone: ToneType::Square, pitch: 440.0, duration: 1.5 };
+        let file = AudioType::File { file: FileType::Ogg, path: "song.ogg".to_owned() };
+
+        for audio_type in &[tone, file] {
+            let serialized = serde_json::to_string(audio_type).unwrap();
+            assert_eq!(&serde_json::from_str::<AudioType>(&serialized).unwrap(), audio_type);
+        }
+
+        let update = AudioUpdate { volume: 0.5, loop_count: -1, ..Default::default() };
+        let update: AudioUpdate = serde_json::from_str(&serde_json::to_string(&update).unwrap()).unwrap();
+        assert_eq!(update.volume, 0.5);
+        assert_eq!(update.loop_count, -1);
+
+        let status = AudioStatus::from_json(json::parse(r#"{
+            "Name": "rust_audio_0", "Type": "tone", "ID": 1, "Volume": 1.0, "Duration": 1000,
+            "Remaining": 500, "Paused": false, "Loop": 0, "Extra": 1,
+            "StartTime": "2020-08-01T12:00:00Z", "EndTime": "2020-08-01T12:00:01Z"
+        }"#).unwrap()).unwrap();
+        let deserialized: AudioStatus = serde_json::from_str(&serde_json::to_string(&status).unwrap()).unwrap();
+        assert_eq!(deserialized, AudioStatus { extra: HashMap::new(), ..status });
+    }
+
+    #[test]
+    fn test_wait_until_ready_mock_clock() {
+        // a status without the expected fields, so it is never ready
+        let backend: Arc<dyn AudioBackend> = Arc::new(MockBackend::with_status("{}"));
+
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let clock = clock::MockClock::install();
+        let real_start = Instant::now();
+        let result = wait_until_ready_on(&backend, "status.json", Duration::from_secs(60));
+        clock::MockClock::uninstall();
+
+        // the mock clock makes a timeout return without really waiting
+        assert!(real_start.elapsed() < Duration::from_secs(5));
+        match result {
+            Err(AudioError::Timeout(msg)) => assert_eq!(msg, "Timed out while waiting for status.json to be ready."),
+            r => panic!("unexpected result {:?}", r)
+        }
+        assert!(clock.elapsed() > Duration::from_secs(60));
+        assert_eq!(clock.sleep_count() as u128, 60_000 / POLL_INTERVAL.as_millis() + 1);
+    }
+
+    #[test]
+    fn test_true_end_time_mock_clock() {
+        let mut source = source_status("rust_audio_test_end_time", 4, 1.0);
+        source["Loop"] = 1.into();
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source])));
+        let audio = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .backend(backend as Arc<dyn AudioBackend>)
+            .audio_with_id(4, "rust_audio_test_end_time");
+
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let clock = clock::MockClock::install();
+        let before = audio.true_end_time().unwrap().unwrap();
+        clock.advance(Duration::from_secs(60));
+        let after = audio.true_end_time().unwrap().unwrap();
+        clock::MockClock::uninstall();
+
+        // the same status, so the end time moves with the mock clock
+        assert_eq!(after - before, chrono::Duration::seconds(60));
+    }
+
+    #[test]
+    fn test_wait_for_start_sleeps() {
+        let backend: Arc<dyn AudioBackend> = Arc::new(MockBackend::with_status(&status_with(Vec::new())));
+
+        let _lock = CLOCK_LOCK.lock().unwrap();
+        let clock = clock::MockClock::install();
+        let result = wait_for_start(&backend, "rust_audio_test_missing", None, Duration::from_secs(2));
+        clock::MockClock::uninstall();
+
+        assert!(matches!(result, Err(AudioError::Timeout(_))));
+        assert_eq!(clock.sleep_count() as u128, 2000 / POLL_INTERVAL.as_millis() + 1);
+    }
+
+    #[test]
+    fn test_parse_time() {
+        let expected = NaiveDate::from_ymd_opt(2020, 8, 1).unwrap().and_hms_milli_opt(12, 30, 15, 250).unwrap();
+        assert_eq!(parse_time("2020-08-01T12:30:15.250000000Z", "start").unwrap(), expected);
+        assert_eq!(parse_time("2020-08-01T12:30:15.250Z", "start").unwrap(), expected);
+        assert_eq!(parse_time("2020-08-01T12:30:15.25Z", "start").unwrap(), expected);
+        assert_eq!(parse_time("2020-08-01T12:30:15.250000Z", "start").unwrap(), expected);
+        assert_eq!(parse_time("2020-08-01T12:30:15.250", "start").unwrap(), expected);
+        assert_eq!(parse_time("2020-08-01T14:30:15.250+02:00", "start").unwrap(), expected);
+
+        let expected = NaiveDate::from_ymd_opt(2020, 8, 1).unwrap().and_hms_opt(12, 30, 15).unwrap();
+        assert_eq!(parse_time("2020-08-01T12:30:15Z", "start").unwrap(), expected);
+        assert_eq!(parse_time("2020-08-01T12:30:15", "start").unwrap(), expected);
+
+        assert_eq!(parse_time("2020-08-01T12:30:15.000Z", "start").unwrap(), expected);
+        assert_eq!(parse_time("2020-08-01T12:30:15z", "start").unwrap(), expected);
+        assert_eq!(parse_time(" 2020-08-01T12:30:15Z\n", "start").unwrap(), expected);
+
+        // every number of fractional digits, including more than chrono can parse
+        let expected = NaiveDate::from_ymd_opt(2020, 8, 1).unwrap().and_hms_nano_opt(12, 30, 15, 123_456_789).unwrap();
+        for digits in 1..=12 {
+            let fraction = &"123456789999"[..digits];
+            let truncated = &fraction[..digits.min(9)];
+            let expected = expected.with_nanosecond(format!("{:0<9}", truncated).parse().unwrap()).unwrap();
+
+            assert_eq!(parse_time(&format!("2020-08-01T12:30:15.{}Z", fraction), "start").unwrap(), expected);
+            assert_eq!(parse_time(&format!("2020-08-01T12:30:15.{}", fraction), "end").unwrap(), expected);
+            assert_eq!(parse_time(&format!("2020-08-01T13:30:15.{}+01:00", fraction), "start").unwrap(), expected);
+        }
+
+        assert!(parse_time("2020-08-01 12:30", "start").is_err());
+        assert!(parse_time("2020-08-01T12:30:15.Z", "start").is_err());
+        assert!(parse_time("", "start").is_err());
+        assert_eq!(parse_time("yesterday", "end").unwrap_err().to_string(),
+            "Error in parsing end time \"yesterday\". (input contains invalid characters)");
+    }
+
+    #[test]
+    fn test_negative_remaining() {
+        assert_eq!(remaining_from_status(&json::parse(r#"{ "Remaining": -1 }"#).unwrap()), 0);
+        assert_eq!(remaining_from_status(&json::parse(r#"{ "Remaining": 1500 }"#).unwrap()), 1500);
+        assert_eq!(remaining_from_status(&json::parse("{}").unwrap()), 0);
+
+        let status = json::parse(r#"{
+            "Name": "rust_audio_0", "Type": "tone", "ID": 1,
+            "Volume": 0.5, "Duration": 2000, "Remaining": -1, "Paused": false, "Loop": 2,
+            "StartTime": "2020-08-01T12:00:00.000000000Z", "EndTime": "2020-08-01T12:00:02.000000000Z"
+        }"#).unwrap();
+        assert_eq!(AudioStatus::from_json(status).unwrap().remaining, 0);
+    }
+
+    #[test]
+    fn test_progress_from_status() {
+        assert_eq!(progress_from_status(&json::parse(r#"{ "Duration": 2000, "Remaining": 500 }"#).unwrap()), 0.75);
+        assert_eq!(progress_from_status(&json::parse(r#"{ "Duration": 2000, "Remaining": 2500 }"#).unwrap()), 0.0);
+        assert_eq!(progress_from_status(&json::parse(r#"{ "Duration": 2000, "Remaining": -1 }"#).unwrap()), 1.0);
+        assert_eq!(progress_from_status(&json::parse(r#"{ "Duration": 0, "Remaining": 0 }"#).unwrap()), 0.0);
+    }
+
+    #[test]
+    fn test_finished_from_status() {
+        assert!(finished_from_status(None));
+        assert!(finished_from_status(Some(&json::parse(r#"{ "Remaining": 0, "Loop": 0 }"#).unwrap())));
+        assert!(finished_from_status(Some(&json::parse(r#"{ "Remaining": -1, "Loop": 0 }"#).unwrap())));
+        assert!(!finished_from_status(Some(&json::parse(r#"{ "Remaining": 0, "Loop": 2 }"#).unwrap())));
+        assert!(!finished_from_status(Some(&json::parse(r#"{ "Remaining": 0, "Loop": -1 }"#).unwrap())));
+        assert!(!finished_from_status(Some(&json::parse(r#"{ "Remaining": 500, "Loop": 0 }"#).unwrap())));
+    }
+
+    #[test]
+    fn test_is_mono_from_status() {
+        assert_eq!(is_mono_from_status(&json::parse(r#"{ "Channels": 1 }"#).unwrap()), Some(true));
+        assert_eq!(is_mono_from_status(&json::parse(r#"{ "Channels": 2 }"#).unwrap()), Some(false));
+        assert_eq!(is_mono_from_status(&json::parse("{}").unwrap()), None);
+    }
+
+    #[test]
+    fn test_file_type_from_path_default() {
+        assert_eq!(file_type_from_path(Path::new("audio.mp3"), Some(FileType::Wav)).unwrap(), FileType::Mp3);
+        assert_eq!(file_type_from_path(Path::new("audio"), Some(FileType::Wav)).unwrap(), FileType::Wav);
+        assert_eq!(file_type_from_path(Path::new("audio.xyz"), Some(FileType::Aiff)).unwrap(), FileType::Aiff);
+        assert!(file_type_from_path(Path::new("audio"), None).is_err());
+        assert!(file_type_from_path(Path::new("audio.xyz"), None).is_err());
+    }
+
+    #[test]
+    fn test_check_volume() {
+        assert!(check_volume(0.0).is_ok());
+        assert!(check_volume(0.5).is_ok());
+        assert!(check_volume(1.0).is_ok());
+        assert!(check_volume(-0.01).is_err());
+        assert!(check_volume(1.01).is_err());
+        assert!(check_volume(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_from_file() {
+        let builder = AudioBuilder::from_file("sounds/AUDIO.WAV").unwrap();
+        assert_eq!(builder.audio_type, AudioType::File { file: FileType::Wav, path: "sounds/AUDIO.WAV".to_owned() });
+
+        let builder = AudioBuilder::from_file("music.Aif").unwrap();
+        assert_eq!(builder.audio_type, AudioType::File { file: FileType::Aiff, path: "music.Aif".to_owned() });
+
+        assert!(file_type_from_path(Path::new("sounds/audio"), None).is_err());
+        assert!(file_type_from_path(Path::new("sounds.wav/audio"), None).is_err());
+        assert_eq!(file_type_from_path(Path::new("SONG.MP3"), None).unwrap(), FileType::Mp3);
+        assert_eq!(file_type_from_path(Path::new("song.ogg"), None).unwrap(), FileType::Ogg);
+        assert_eq!(file_type_from_path(Path::new("song.FLAC"), None).unwrap(), FileType::Flac);
+    }
+
+    #[test]
+    fn test_file_type_str_round_trip() {
+        for file in &[FileType::Wav, FileType::Aiff, FileType::Mp3, FileType::Ogg, FileType::Flac] {
+            assert_eq!(FileType::from_type_str(file.as_str()), Some(*file));
+        }
+
+        assert_eq!(FileType::Ogg.as_str(), "ogg");
+        assert_eq!(FileType::Flac.as_str(), "flac");
+        assert_eq!(FileType::from_type_str("tone"), None);
+    }
+
+    #[test]
+    fn test_loop_count_enables_looping() {
+        let mut source = source_status("rust_audio_test_loop_count", 12, 1.0);
+        source["Loop"] = 2.into();
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source])));
+
+        let audio = AudioBuilder::new(&AudioType::Tone { tone: ToneType::Sine, pitch: 440.0, duration: 1.0 })
+            .name("rust_audio_test_loop_count")
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .loop_count(2)
+            .build()
+            .unwrap();
+
+        assert_eq!(audio.configured_loop_count(), 2);
+        let command = backend.commands().remove(0);
+        assert_eq!(command["DoesLoop"], true);
+        assert_eq!(command["LoopCount"], 2);
+
+        let tone = AudioType::Tone { tone: ToneType::Sine, pitch: 440.0, duration: 1.0 };
+        assert!(!AudioBuilder::new(&tone).loop_count(0).does_loop);
+        assert!(!AudioBuilder::new(&tone).loop_count(2).does_loop(false).does_loop);
+    }
+
+    #[test]
+    fn test_file_and_tone_constructors() {
+        let builder = AudioBuilder::file(FileType::Mp3, "whoosh.mp3");
+        assert_eq!(builder.audio_type, AudioType::File { file: FileType::Mp3, path: "whoosh.mp3".to_owned() });
+        assert_eq!(builder.volume, 1.0);
+
+        let builder = AudioBuilder::tone(ToneType::Saw, 220.0, 0.5);
+        assert_eq!(builder.audio_type, AudioType::Tone { tone: ToneType::Saw, pitch: 220.0, duration: 0.5 });
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(AudioType::Tone { tone: ToneType::Square, pitch: 440.0, duration: 2.0 }.to_string(), "tone(square, 440Hz, 2s)");
+        assert_eq!(AudioType::Tone { tone: ToneType::Saw, pitch: 261.63, duration: 0.5 }.to_string(), "tone(saw, 261.63Hz, 0.5s)");
+        assert_eq!(AudioType::File { file: FileType::Wav, path: "audio.wav".to_owned() }.to_string(), "file(wav, audio.wav)");
+        assert_eq!(FileType::Aiff.to_string(), "aiff");
+        assert_eq!(ToneType::Triangle.to_string(), "triangle");
+    }
+
+    #[test]
+    fn test_from_str() {
+        for file in &[FileType::Wav, FileType::Aiff, FileType::Mp3, FileType::Ogg, FileType::Flac] {
+            assert_eq!(file.to_string().parse::<FileType>().unwrap(), *file);
+            assert_eq!(file.to_string().to_uppercase().parse::<FileType>().unwrap(), *file);
+        }
+
+        for tone in &[ToneType::Sine, ToneType::Triangle, ToneType::Saw, ToneType::Square] {
+            assert_eq!(tone.to_string().parse::<ToneType>().unwrap(), *tone);
+        }
+
+        assert_eq!("Square".parse::<ToneType>().unwrap(), ToneType::Square);
+        assert!("wave".parse::<FileType>().is_err());
+        assert!("".parse::<FileType>().is_err());
+        assert!("sawtooth".parse::<ToneType>().is_err());
+        assert_eq!("noise".parse::<ToneType>().unwrap_err().to_string(), "Unknown tone type noise.");
+    }
+
+    #[test]
+    fn test_with_type() {
+        let base = AudioBuilder::new(&AudioType::File { file: FileType::Wav, path: "click.wav".to_owned() })
+            .volume(0.5)
+            .does_loop(true)
+            .loop_count(3);
+        let whoosh = AudioType::File { file: FileType::Mp3, path: "whoosh.mp3".to_owned() };
+        let builder = base.with_type(&whoosh);
+
+        assert_eq!(builder.audio_type, whoosh);
+        assert_eq!(builder.volume, 0.5);
+        assert!(builder.does_loop);
+        assert_eq!(builder.loop_count, 3);
+        assert_eq!(base.audio_type, AudioType::File { file: FileType::Wav, path: "click.wav".to_owned() });
+    }
+
+    #[test]
+    fn test_true_end_time_from() {
+        let now = Utc::now();
+        assert_eq!(true_end_time_from(now, 500, 2000, -1), None);
+        assert_eq!(true_end_time_from(now, 500, 2000, 0), Some(now + chrono::Duration::milliseconds(500)));
+        assert_eq!(true_end_time_from(now, 500, 2000, 2), Some(now + chrono::Duration::milliseconds(4500)));
+    }
+
+    #[test]
+    fn test_snapshot_from_status() {
+        let status = json::parse(r#"{
+            "Sources": [
+                {
+                    "Name": "rust_audio_0", "Type": "tone", "ID": 1,
+                    "Volume": 0.5, "Pan": -0.5, "Duration": 2000, "Remaining": 500, "Paused": true, "Loop": 0,
+                    "Args": { "WaveType": 3, "Pitch": 440.0, "Seconds": 2.0 }
+                },
+                {
+                    "Name": "rust_audio_1", "Type": "wav", "ID": 2,
+                    "Volume": 1.0, "Duration": 3000, "Remaining": 3000, "Paused": false, "Loop": -1,
+                    "Args": { "Path": "audio.wav" }
+                },
+                {
+                    "Name": "rust_audio_2", "Type": "ogg", "ID": 3,
+                    "Volume": 1.0, "Duration": 3000, "Remaining": 3000, "Paused": false, "Loop": 0
+                }
+            ],
+            "Running": true,
+            "Disabled": false
+        }"#).unwrap();
+
+        let snapshot = snapshot_from_status(Arc::new(MockBackend::new()), &status);
+        assert_eq!(snapshot.sources.len(), 2);
+
+        let tone = &snapshot.sources[0];
+        assert_eq!(tone.audio_type, AudioType::Tone { tone: ToneType::Square, pitch: 440.0, duration: 2.0 });
+        assert_eq!(tone.volume, 0.5);
+        assert_eq!(tone.pan, -0.5);
+        assert!(tone.paused);
+        assert_eq!(tone.loop_count, 0);
+        assert_eq!(tone.position, 1500);
+
+        let file = &snapshot.sources[1];
+        assert_eq!(file.audio_type, AudioType::File { file: FileType::Wav, path: "audio.wav".to_owned() });
+        assert_eq!(file.pan, 0.0);
+        assert_eq!(file.loop_count, -1);
+        assert_eq!(file.position, 0);
+    }
+
+    #[test]
+    fn test_follow_skips_nan() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_follow_nan", 3, 1.0)])));
+        let audio = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .audio_with_id(3, "rust_audio_test_follow_nan");
+
+        let mut samples = vec![0.5, f64::NAN, 0.8].into_iter();
+        let follower = audio.follow(Duration::from_millis(1), move || samples.next().unwrap_or(0.8));
+
+        let start = Instant::now();
+        while backend.commands().len() < 2 && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(5));
+        }
+        drop(follower);
+
+        let volumes = backend.commands().iter().map(|c| c["Volume"].as_f64().unwrap()).collect::<Vec<_>>();
+        assert_eq!(volumes, vec![0.5, 0.8]);
+    }
+
+    #[test]
+    fn test_mute_soft_mode() {
+        // the status cannot be read, like when not running on repl.it
+        let backend = Arc::new(MockBackend::new());
+        let mut audio = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .audio_with_id(3, "rust_audio_test_mute_soft");
+
+        TEST_SOFT_MODE.with(|m| m.set(true));
+        let muted = audio.mute();
+        let unmuted = audio.unmute();
+        TEST_SOFT_MODE.with(|m| m.set(false));
+
+        muted.unwrap();
+        unmuted.unwrap();
+        assert!(!audio.is_muted());
+        assert!(backend.commands().is_empty());
+        assert!(audio.mute().is_err());
+    }
+
+    #[test]
+    fn test_set_loop_count_preserving_position() {
+        let mut source = source_status("rust_audio_test_preserve", 3, 0.7);
+        source["Paused"] = true.into();
+        source["Loop"] = 2.into();
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source])));
+        let mut audio = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .audio_with_id(3, "rust_audio_test_preserve");
+
+        audio.set_loop_count_preserving_position(-1).unwrap();
+
+        // only the loop count changes, and the volume is unchanged
+        let commands = backend.commands();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0]["ID"], 3);
+        assert_eq!(commands[0]["Volume"], 0.7);
+        assert_eq!(commands[0]["Paused"], true);
+        assert_eq!(commands[0]["DoesLoop"], true);
+        assert_eq!(commands[0]["LoopCount"], -1);
+        assert_eq!(audio.get_volume().unwrap(), 0.7);
+    }
+
+    #[test]
+    fn test_restore_snapshot() {
+        let mut source = source_status("rust_audio_test_snapshot", 1, 0.5);
+        source["Pan"] = (-0.5).into();
+        source["Paused"] = true.into();
+        source["Loop"] = 2.into();
+        source["Args"] = object! { WaveType: 1, Pitch: 440.0, Seconds: 1.0 };
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source])));
+
+        let snapshot = snapshot_all_from(Arc::clone(&backend) as Arc<dyn AudioBackend>).unwrap();
+        backend.set_status_fn(playing_status(|_| true));
+        let audios = snapshot.restore().unwrap();
+        assert_eq!(audios.len(), 1);
+
+        // played and paused on the backend of the snapshot, with the same pan
+        let commands = backend.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0]["Args"]["Pitch"], 440.0);
+        assert_eq!(commands[0]["Pan"], -0.5);
+        assert_eq!(commands[0]["DoesLoop"], true);
+        assert_eq!(commands[0]["LoopCount"], 2);
+        assert_eq!(commands[1]["ID"], audios[0].get_id());
+        assert_eq!(commands[1]["Paused"], true);
+        assert_eq!(commands[1]["Pan"], -0.5);
+        assert_eq!(commands[1]["Volume"], 0.5);
+    }
+}
@@ -0,0 +1,362 @@
+//! Where commands are written to and where the status is read from.
+
+use std::env;
+use std::fs;
+use std::collections::HashMap;
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use lazy_static::lazy_static;
+
+use crate::audio::{AudioError, AudioResult, STATUS_PATH_ENV_VAR, UPDATE_PATH_ENV_VAR};
+use crate::writer;
+
+const DEFAULT_UPDATE_PATH: &str = "/tmp/audio";
+const DEFAULT_STATUS_PATH: &str = "/tmp/audioStatus.json";
+
+// a weak reference to a backend and its id
+type BackendEntry = (Weak<dyn AudioBackend>, u64);
+
+lazy_static! {
+    static ref DEFAULT_BACKEND: Arc<dyn AudioBackend> = Arc::new(ReplitBackend);
+    static ref CURRENT_BACKEND_ID: AtomicU64 = AtomicU64::new(0);
+    // ids of the backends that are in use, by their address, where the weak reference keeps
+    // another backend from being allocated at the same address while the entry exists
+    static ref BACKEND_IDS: Mutex<HashMap<usize, BackendEntry>> = Mutex::new(HashMap::new());
+}
+
+/// Something that plays audio: it accepts commands like the repl.it audio playing program,
+/// and reports the status of its audio sources in the same format as the status file.
+///
+/// Audio played with an `AudioBuilder` uses the backend of the builder (see
+/// `AudioBuilder::backend`), which is `ReplitBackend` by default. Free functions, like
+/// `list_sources` and `stop_all`, always use `ReplitBackend`, unless they take a backend,
+/// like `snapshot_all_from`.
+pub trait AudioBackend: Send + Sync {
+    /// Write a single command, which is a JSON object.
+    fn write_command(&self, command: &str) -> AudioResult<()>;
+
+    /// Read the status, which is a JSON object with the `Sources`, `Running`, and
+    /// `Disabled` fields.
+    fn read_status(&self) -> AudioResult<String>;
+}
+
+/// The backend for the repl.it audio playing program, which appends commands to
+/// `/tmp/audio` and reads the status from `/tmp/audioStatus.json`.
+///
+/// The paths can be overridden with the environment variables named by
+/// `UPDATE_PATH_ENV_VAR` and `STATUS_PATH_ENV_VAR`. Commands from all threads are written
+/// by a single thread, so they are never interleaved, and each command is written while
+/// holding an advisory lock on the file, for other programs that also lock it. If either
+/// file does not exist, like when not running on repl.it, then `AudioError::NotOnReplit`
+/// is returned.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplitBackend;
+
+impl AudioBackend for ReplitBackend {
+    fn write_command(&self, command: &str) -> AudioResult<()> {
+        let path = update_path();
+
+        match writer::write(&path, command.to_owned()) {
+            Err(AudioError::Io(e)) => Err(io_error(path, e)),
+            r => r
+        }
+    }
+
+    fn read_status(&self) -> AudioResult<String> {
+        let path = status_path();
+        fs::read_to_string(&path).map_err(|e| io_error(path, e))
+    }
+}
+
+// a missing file almost always means that the program is not running on repl.it
+fn io_error(path: String, e: io::Error) -> AudioError {
+    if e.kind() == io::ErrorKind::NotFound {
+        AudioError::NotOnReplit { path }
+    } else {
+        AudioError::Io(e)
+    }
+}
+
+/// A backend for tests, which records every command that is written to it and serves a
+/// status that is set with `set_status`.
+///
+/// Audio instances are only found after they are played if the status has an audio source
+/// with their name, so audio instances should be played with a custom name (see
+/// `AudioBuilder::name`). The status never changes on its own: commands are not applied to
+/// it, so updates and stops are only recorded. For a status that follows the commands, like
+/// a simple audio playing program, use `set_status_fn`.
+///
+/// This is only available with the `mock-backend` feature.
+#[cfg(any(test, feature = "mock-backend"))]
+pub struct MockBackend {
+    status: Mutex<MockStatus>,
+    commands: Mutex<Vec<json::JsonValue>>
+}
+
+#[cfg(any(test, feature = "mock-backend"))]
+type StatusFn = Box<dyn Fn(&[json::JsonValue]) -> String + Send + Sync>;
+
+#[cfg(any(test, feature = "mock-backend"))]
+enum MockStatus {
+    Fixed(String),
+    // computed from the commands every time it is read
+    Computed(StatusFn)
+}
+
+#[cfg(any(test, feature = "mock-backend"))]
+impl MockBackend {
+    /// Create a new `MockBackend` with a status that has no audio sources.
+    pub fn new() -> Self {
+        MockBackend {
+            status: Mutex::new(MockStatus::Fixed(r#"{ "Sources": [], "Running": false, "Disabled": false }"#.to_owned())),
+            commands: Mutex::new(Vec::new())
+        }
+    }
+
+    /// Create a new `MockBackend` that serves `status`, like with `set_status`.
+    pub fn with_status(status: &str) -> Self {
+        let backend = MockBackend::new();
+        backend.set_status(status);
+        backend
+    }
+
+    /// Set the status that is served, in the same format as the status file.
+    pub fn set_status(&self, status: &str) {
+        *self.status.lock().unwrap() = MockStatus::Fixed(status.to_owned());
+    }
+
+    /// Compute the status from all commands that were written so far, in order, every time
+    /// it is read, instead of serving a status that is set with `set_status`.
+    ///
+    /// Calling `set_status` afterwards serves a fixed status again.
+    pub fn set_status_fn<F: Fn(&[json::JsonValue]) -> String + Send + Sync + 'static>(&self, f: F) {
+        *self.status.lock().unwrap() = MockStatus::Computed(Box::new(f));
+    }
+
+    /// Get all commands that were written so far, in order.
+    pub fn commands(&self) -> Vec<json::JsonValue> {
+        self.commands.lock().unwrap().clone()
+    }
+
+    /// Forget all commands that were written so far.
+    pub fn clear_commands(&self) {
+        self.commands.lock().unwrap().clear();
+    }
+}
+
+#[cfg(any(test, feature = "mock-backend"))]
+impl Default for MockBackend {
+    fn default() -> Self {
+        MockBackend::new()
+    }
+}
+
+#[cfg(any(test, feature = "mock-backend"))]
+impl AudioBackend for MockBackend {
+    fn write_command(&self, command: &str) -> AudioResult<()> {
+        self.commands.lock().unwrap().push(json::parse(command)?);
+        Ok(())
+    }
+
+    fn read_status(&self) -> AudioResult<String> {
+        match &*self.status.lock().unwrap() {
+            MockStatus::Fixed(status) => Ok(status.clone()),
+            MockStatus::Computed(f) => Ok(f(&self.commands.lock().unwrap()))
+        }
+    }
+}
+
+// an audio source that is half way through a tone of 1 second, as it is in the status file
+#[cfg(test)]
+pub(crate) fn source_status(name: &str, id: u64, volume: f64) -> json::JsonValue {
+    json::object! {
+        Name: name,
+        Type: "tone",
+        ID: id,
+        Volume: volume,
+        Duration: 1000,
+        Remaining: 500,
+        Paused: false,
+        Loop: 0,
+        StartTime: "2020-08-01T12:00:00Z",
+        EndTime: "2020-08-01T12:00:01Z"
+    }
+}
+
+// the contents of the status file while the audio sources are playing
+#[cfg(test)]
+pub(crate) fn status_with(sources: Vec<json::JsonValue>) -> String {
+    json::object! {
+        Sources: sources,
+        Running: true,
+        Disabled: false
+    }.dump()
+}
+
+// every audio instance that is played and passes `plays` is playing forever, with IDs
+// starting from 1
+#[cfg(test)]
+pub(crate) fn playing_status(plays: fn(&json::JsonValue) -> bool) -> impl Fn(&[json::JsonValue]) -> String {
+    move |commands| status_with(played(commands, plays)
+        .enumerate()
+        .map(|(i, name)| source_status(name, i as u64 + 1, 1.0))
+        .collect())
+}
+
+// every audio instance that is played and passes `plays` is in the status for a single
+// read, so it finishes right after it begins playing
+#[cfg(test)]
+pub(crate) fn single_read_status(plays: fn(&json::JsonValue) -> bool) -> impl Fn(&[json::JsonValue]) -> String {
+    // the number of commands that were seen and the number of audio sources so far
+    let seen = Mutex::new((0, 0));
+
+    move |commands| {
+        let mut seen = seen.lock().unwrap();
+        // the commands may have been cleared since the last read
+        let sources = match played(&commands[seen.0.min(commands.len())..], plays).last() {
+            Some(name) => {
+                seen.1 += 1;
+                vec![source_status(name, seen.1, 1.0)]
+            },
+            None => Vec::new()
+        };
+
+        seen.0 = commands.len();
+        status_with(sources)
+    }
+}
+
+#[cfg(test)]
+fn played(commands: &[json::JsonValue], plays: fn(&json::JsonValue) -> bool) -> impl Iterator<Item = &str> {
+    commands.iter().filter(move |c| plays(c)).filter_map(|c| c["Name"].as_str())
+}
+
+pub(crate) fn default_backend() -> Arc<dyn AudioBackend> {
+    Arc::clone(&DEFAULT_BACKEND)
+}
+
+pub(crate) fn update_path() -> String {
+    env::var(UPDATE_PATH_ENV_VAR).unwrap_or_else(|_| DEFAULT_UPDATE_PATH.to_owned())
+}
+
+pub(crate) fn status_path() -> String {
+    env::var(STATUS_PATH_ENV_VAR).unwrap_or_else(|_| DEFAULT_STATUS_PATH.to_owned())
+}
+
+// audio source ids are only unique within a backend, so this also includes the id of the
+// backend
+pub(crate) type SourceKey = (u64, u64);
+
+pub(crate) fn source_key(backend: &Arc<dyn AudioBackend>, id: u64) -> SourceKey {
+    (backend_id(backend), id)
+}
+
+// a backend gets a new id the first time it is used, which is never given to another backend,
+// even one that is allocated at the same address after the backend is dropped
+pub(crate) fn backend_id(backend: &Arc<dyn AudioBackend>) -> u64 {
+    let address = Arc::as_ptr(backend) as *const () as usize;
+    let mut ids = BACKEND_IDS.lock().unwrap();
+
+    if let Some(&(_, id)) = ids.get(&address) {
+        return id;
+    }
+
+    // forget dropped backends, which lets their addresses be reused
+    ids.retain(|_, (b, _)| b.strong_count() > 0);
+    let id = CURRENT_BACKEND_ID.fetch_add(1, Ordering::SeqCst);
+    ids.insert(address, (Arc::downgrade(backend), id));
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::audio::{AudioBuilder, AudioType, AudioUpdate, ToneType};
+
+    #[test]
+    fn test_mock_backend() {
+        let backend = Arc::new(MockBackend::new());
+        let builder = AudioBuilder::tone(ToneType::Saw, 220.0, 1.0)
+            .name("rust_audio_test_mock")
+            .volume(0.5)
+            .timeout(std::time::Duration::ZERO)
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>);
+
+        // nothing is playing, like when the audio playing program is not running
+        assert!(matches!(builder.clone().build(), Err(AudioError::NotRunning)));
+        let command = &backend.commands()[0];
+        assert_eq!(command["Name"], "rust_audio_test_mock");
+        assert_eq!(command["Type"], "tone");
+        assert_eq!(command["Volume"], 0.5);
+        assert_eq!(command["Args"]["Pitch"], 220.0);
+        backend.clear_commands();
+
+        let mut source = source_status("rust_audio_test_mock", 4, 0.5);
+        source["Loop"] = 3.into();
+        backend.set_status(&status_with(vec![source]));
+        let mut audio = builder.build().unwrap();
+        audio.pause().unwrap();
+        assert!(audio.update(&AudioUpdate { volume: 2.0, ..Default::default() }).is_err());
+
+        // pausing keeps the other fields from the status
+        let commands = backend.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[1]["ID"], 4);
+        assert_eq!(commands[1]["Paused"], true);
+        assert_eq!(commands[1]["Volume"], 0.5);
+        assert_eq!(commands[1]["LoopCount"], 3);
+    }
+
+    #[test]
+    fn test_mock_backend_status_fn() {
+        let backend = Arc::new(MockBackend::new());
+        backend.set_status_fn(single_read_status(|c| c["Args"]["Pitch"] != 660.0));
+        let builder = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .timeout(std::time::Duration::ZERO)
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>);
+
+        let audio = builder.build().unwrap();
+        assert_eq!(audio.get_id(), 1);
+        // the audio source finished after it was seen once
+        assert!(audio.is_finished().unwrap());
+        assert_eq!(builder.build().unwrap().get_id(), 2);
+        assert!(builder.with_type(&AudioType::Tone { tone: ToneType::Sine, pitch: 660.0, duration: 1.0 }).build().is_err());
+
+        backend.set_status_fn(playing_status(|_| true));
+        let audio = builder.build().unwrap();
+        assert_eq!(audio.get_id(), 4);
+        assert!(!audio.is_finished().unwrap());
+
+        backend.set_status(&status_with(Vec::new()));
+        assert!(audio.is_finished().unwrap());
+    }
+
+    #[test]
+    fn test_io_error() {
+        let missing = io_error("/tmp/audio".to_owned(), io::Error::new(io::ErrorKind::NotFound, "missing"));
+        assert!(matches!(missing, AudioError::NotOnReplit { ref path } if path == "/tmp/audio"));
+        assert_eq!(missing.to_string(), "replit_audio requires the repl.it audio environment (/tmp/audio not found).");
+
+        let denied = io_error("/tmp/audio".to_owned(), io::Error::new(io::ErrorKind::PermissionDenied, "denied"));
+        assert!(matches!(denied, AudioError::Io(_)));
+    }
+
+    #[test]
+    fn test_backend_id() {
+        let backend: Arc<dyn AudioBackend> = Arc::new(MockBackend::new());
+        let other: Arc<dyn AudioBackend> = Arc::new(MockBackend::new());
+        let id = backend_id(&backend);
+        assert_eq!(backend_id(&Arc::clone(&backend)), id);
+        assert_ne!(backend_id(&other), id);
+
+        // a backend created after another one is dropped never gets its id, even if it is
+        // allocated at the same address
+        drop(backend);
+        let next: Arc<dyn AudioBackend> = Arc::new(MockBackend::new());
+        assert_ne!(backend_id(&next), id);
+        assert_eq!(source_key(&next, 1), (backend_id(&next), 1));
+    }
+}
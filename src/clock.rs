@@ -0,0 +1,160 @@
+//! The clock used by all time-based code in the crate, which can be replaced by a mock
+//! clock in tests.
+
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use chrono::{DateTime, Utc};
+
+#[cfg(any(test, feature = "mock-clock"))]
+use std::sync::Mutex;
+
+pub(crate) trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+    fn utc_now(&self) -> DateTime<Utc>;
+    fn sleep(&self, duration: Duration);
+}
+
+struct RealClock;
+
+impl Clock for RealClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn utc_now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+lazy_static! {
+    static ref CLOCK: RwLock<Arc<dyn Clock>> = RwLock::new(Arc::new(RealClock));
+}
+
+#[cfg(test)]
+lazy_static! {
+    // held by tests that depend on the clock, since a mock clock replaces it for all
+    // threads
+    pub(crate) static ref TEST_LOCK: Mutex<()> = Mutex::new(());
+}
+
+pub(crate) fn now() -> Instant {
+    CLOCK.read().unwrap().now()
+}
+
+// the wall clock time, which moves together with `now`
+pub(crate) fn utc_now() -> DateTime<Utc> {
+    CLOCK.read().unwrap().utc_now()
+}
+
+pub(crate) fn sleep(duration: Duration) {
+    // clone the clock so sleeping does not hold the lock
+    let clock = Arc::clone(&CLOCK.read().unwrap());
+    clock.sleep(duration);
+}
+
+pub(crate) fn elapsed(since: Instant) -> Duration {
+    now().saturating_duration_since(since)
+}
+
+/// A clock that only moves forward when it is advanced or slept on, which allows testing
+/// time-based code without real delays.
+///
+/// This is only available with the `mock-clock` feature.
+#[cfg(any(test, feature = "mock-clock"))]
+pub struct MockClock {
+    start: Instant,
+    start_utc: DateTime<Utc>,
+    state: Mutex<MockState>
+}
+
+#[cfg(any(test, feature = "mock-clock"))]
+struct MockState {
+    offset: Duration,
+    sleeps: usize
+}
+
+#[cfg(any(test, feature = "mock-clock"))]
+impl MockClock {
+    /// Replace the clock used by the crate with a new `MockClock` and return it.
+    ///
+    /// Sleeping on the `MockClock` returns immediately after advancing it. The wall clock
+    /// time, like the current time used by `Audio::true_end_time`, starts at the real time
+    /// and moves forward together with the `MockClock`.
+    pub fn install() -> Arc<MockClock> {
+        let clock = Arc::new(MockClock {
+            start: Instant::now(),
+            start_utc: Utc::now(),
+            state: Mutex::new(MockState { offset: Duration::ZERO, sleeps: 0 })
+        });
+        *CLOCK.write().unwrap() = Arc::clone(&clock) as Arc<dyn Clock>;
+        clock
+    }
+
+    /// Replace the clock used by the crate with the real clock again.
+    pub fn uninstall() {
+        *CLOCK.write().unwrap() = Arc::new(RealClock);
+    }
+
+    /// Move the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        self.state.lock().unwrap().offset += duration;
+    }
+
+    /// Get the total time that the clock was moved forward by.
+    pub fn elapsed(&self) -> Duration {
+        self.state.lock().unwrap().offset
+    }
+
+    /// Get the number of times that the clock was slept on.
+    pub fn sleep_count(&self) -> usize {
+        self.state.lock().unwrap().sleeps
+    }
+}
+
+#[cfg(any(test, feature = "mock-clock"))]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.state.lock().unwrap().offset
+    }
+
+    fn utc_now(&self) -> DateTime<Utc> {
+        let offset = self.state.lock().unwrap().offset;
+        self.start_utc + chrono::Duration::from_std(offset).unwrap_or_else(|_| chrono::Duration::zero())
+    }
+
+    fn sleep(&self, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.offset += duration;
+        state.sleeps += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock() {
+        let clock = MockClock {
+            start: Instant::now(),
+            start_utc: Utc::now(),
+            state: Mutex::new(MockState { offset: Duration::ZERO, sleeps: 0 })
+        };
+        let start = clock.now();
+        let start_utc = clock.utc_now();
+
+        clock.advance(Duration::from_secs(1));
+        clock.sleep(Duration::from_secs(2));
+        assert_eq!(clock.now() - start, Duration::from_secs(3));
+        assert_eq!(clock.utc_now() - start_utc, chrono::Duration::seconds(3));
+        assert_eq!(clock.elapsed(), Duration::from_secs(3));
+        assert_eq!(clock.sleep_count(), 1);
+    }
+}
@@ -0,0 +1,157 @@
+//! Playing audio files from the web by downloading them to a temporary file first.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+
+use crate::audio::{AudioBuilder, AudioError, AudioResult, AudioType, FileType};
+
+lazy_static! {
+    static ref CURRENT_DOWNLOAD: AtomicU64 = AtomicU64::new(0);
+}
+
+// a downloaded file, which is removed once it is dropped
+struct TempFile {
+    path: PathBuf
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                log::warn!("Error in removing downloaded file {}. ({})", self.path.display(), e);
+            },
+            _ => ()
+        }
+    }
+}
+
+impl AudioBuilder {
+    /// Download the audio file at `url` to a temporary file, and create a new
+    /// `AudioBuilder` for it, like with `AudioBuilder::file`.
+    ///
+    /// This blocks until the whole file is downloaded. An error is returned if the request
+    /// fails, the response status is not `200`, or the path of the temporary file is not
+    /// valid UTF-8 (see `AudioType::file`). The temporary file is removed once the
+    /// builder, its clones, and all audio instances played with them are dropped, so an
+    /// `Audio` should be kept around while it is playing.
+    ///
+    /// This is only available with the `download` feature.
+    pub fn from_url(url: &str, file: FileType) -> AudioResult<AudioBuilder> {
+        let id = CURRENT_DOWNLOAD.fetch_add(1, Ordering::SeqCst);
+        let temp = TempFile {
+            path: env::temp_dir().join(format!("replit_audio_download_{}_{}.{}", process::id(), id, file.as_str()))
+        };
+
+        // checked before downloading, so a path that is not valid UTF-8 is not downloaded to
+        let audio_type = AudioType::file(file, &temp.path)?;
+
+        // the partially downloaded file is removed if this fails
+        download(url, &temp)?;
+
+        let mut builder = AudioBuilder::new(&audio_type);
+        builder.keep_alive = Some(Arc::new(temp));
+        Ok(builder)
+    }
+}
+
+fn download(url: &str, temp: &TempFile) -> AudioResult<()> {
+    let response = match ureq::get(url).call() {
+        Ok(r) => r,
+        Err(ureq::Error::Status(code, _)) => {
+            return Err(AudioError::new(format!("HTTP error {} in downloading {}.", code, url)));
+        },
+        Err(e) => return Err(AudioError::new(format!("Error in downloading {}. ({})", url, e)))
+    };
+
+    // other successful statuses, like 204, do not have the file
+    if response.status() != 200 {
+        return Err(AudioError::new(format!("Unexpected HTTP status {} in downloading {}.", response.status(), url)));
+    }
+
+    let mut file = fs::File::create(&temp.path)?;
+    io::copy(&mut response.into_reader(), &mut file)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use crate::audio::AudioType;
+    use crate::backend::{source_status, status_with, AudioBackend, MockBackend};
+
+    // serves a single request with the response, and returns its url
+    fn serve(response: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/beep.wav", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+
+            while !request.ends_with(b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        url
+    }
+
+    #[test]
+    fn test_from_url() {
+        let url = serve("HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\nRIFF");
+        let builder = AudioBuilder::from_url(&url, FileType::Wav).unwrap();
+
+        let path = match &builder.audio_type {
+            AudioType::File { file: FileType::Wav, path } => path.clone(),
+            t => panic!("unexpected audio type {:?}", t)
+        };
+        assert!(path.ends_with(".wav"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "RIFF");
+
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_download", 1, 1.0)])));
+        let audio = builder.name("rust_audio_test_download")
+            .backend(backend as Arc<dyn AudioBackend>)
+            .build()
+            .unwrap();
+
+        // the audio instance keeps the file after the builder is dropped
+        assert!(fs::metadata(&path).is_ok());
+        drop(audio);
+        assert!(fs::metadata(&path).is_err());
+    }
+
+    #[test]
+    fn test_from_url_errors() {
+        let url = serve("HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let err = AudioBuilder::from_url(&url, FileType::Mp3).err().unwrap();
+        assert_eq!(err.to_string(), format!("HTTP error 404 in downloading {}.", url));
+
+        let url = serve("HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n");
+        let err = AudioBuilder::from_url(&url, FileType::Mp3).err().unwrap();
+        assert_eq!(err.to_string(), format!("Unexpected HTTP status 204 in downloading {}.", url));
+
+        // nothing is listening on the port anymore
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/beep.wav", listener.local_addr().unwrap())
+        };
+        let err = AudioBuilder::from_url(&url, FileType::Mp3).err().unwrap();
+        assert!(err.to_string().starts_with(&format!("Error in downloading {}. (", url)));
+    }
+}
@@ -0,0 +1,305 @@
+//! Automatic ducking of lower priority audio sources while higher priority ones play.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+
+use crate::audio::{self, AudioUpdate};
+use crate::backend::{self, AudioBackend, SourceKey};
+use crate::clock;
+
+const DUCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+#[derive(Clone)]
+struct Registered {
+    backend: Arc<dyn AudioBackend>,
+    priority: u8,
+    // the volume from before the audio source was ducked, if it is ducked
+    ducked: Option<f64>
+}
+
+struct DuckState {
+    enabled: bool,
+    amount: f64,
+    watcher_running: bool,
+    // audio sources played by this program while automatic ducking is enabled
+    sources: HashMap<SourceKey, Registered>
+}
+
+lazy_static! {
+    static ref DUCK_STATE: Mutex<DuckState> = Mutex::new(DuckState {
+        enabled: false,
+        amount: 0.0,
+        watcher_running: false,
+        sources: HashMap::new()
+    });
+}
+
+/// Set whether audio sources are automatically ducked while an audio source with a higher
+/// priority (see `AudioBuilder::priority`) is playing, and by how much.
+///
+/// While ducked, the volume of an audio source is multiplied by `1.0 - amount`, so the
+/// `amount` is clamped to the range `[0.0, 1.0]`. Once no higher priority audio source is
+/// playing, the volume from before ducking is restored. Paused audio sources do not cause
+/// ducking. Only audio instances that begin playing while automatic ducking is enabled are
+/// ducked or cause ducking, and only other audio instances with the same backend are
+/// ducked, so audio sources played by other programs are never ducked.
+///
+/// A background thread polls the status file every 50 milliseconds, so ducking and
+/// restoring can lag behind an audio source starting or ending by that much, plus the time
+/// the repl.it audio playing program takes to apply the update. Volume changes made to a
+/// ducked audio source are overwritten when it is restored. Disabling automatic ducking
+/// restores all ducked audio sources.
+///
+/// By default, automatic ducking is disabled.
+pub fn set_auto_duck(enabled: bool, amount: f64) {
+    let mut state = DUCK_STATE.lock().unwrap();
+    state.enabled = enabled;
+    state.amount = amount.clamp(0.0, 1.0);
+
+    if enabled && !state.watcher_running {
+        state.watcher_running = true;
+        // a dedicated thread, since watching runs until ducking is disabled
+        thread::spawn(watch);
+    }
+}
+
+pub(crate) fn register(backend: &Arc<dyn AudioBackend>, id: u64, priority: u8) {
+    let mut state = DUCK_STATE.lock().unwrap();
+
+    if state.enabled {
+        let registered = Registered { backend: Arc::clone(backend), priority, ducked: None };
+        state.sources.insert(backend::source_key(backend, id), registered);
+    }
+}
+
+// the lock is only held to copy and update the state, never while reading the status or
+// writing updates
+fn watch() {
+    loop {
+        let (enabled, amount, sources) = {
+            let state = DUCK_STATE.lock().unwrap();
+            (state.enabled, state.amount, state.sources.clone())
+        };
+
+        if !enabled {
+            restore_all();
+
+            let mut state = DUCK_STATE.lock().unwrap();
+
+            // ducking may have been enabled again while restoring
+            if !state.enabled {
+                state.sources.clear();
+                state.watcher_running = false;
+                return;
+            }
+
+            continue;
+        }
+
+        for (backend, sources) in by_backend(sources) {
+            match apply(&backend, &sources, amount) {
+                Ok(changes) => {
+                    let mut state = DUCK_STATE.lock().unwrap();
+
+                    for (id, change) in changes {
+                        let key = backend::source_key(&backend, id);
+
+                        match change {
+                            Change::Gone => {
+                                state.sources.remove(&key);
+                            },
+                            Change::Ducked(volume) => {
+                                if let Some(r) = state.sources.get_mut(&key) {
+                                    r.ducked = volume;
+                                }
+                            }
+                        }
+                    }
+                },
+                Err(e) => log::debug!("Error in ducking audio sources. ({})", e)
+            }
+        }
+
+        clock::sleep(DUCK_POLL_INTERVAL);
+    }
+}
+
+enum Change {
+    Gone,
+    // the new volume from before ducking, if the audio source is ducked
+    Ducked(Option<f64>)
+}
+
+// the registered audio sources of a backend, by id
+type BackendSources = (Arc<dyn AudioBackend>, HashMap<u64, Registered>);
+
+// the registered audio sources grouped by their backend, since each backend has its own status
+fn by_backend(sources: HashMap<SourceKey, Registered>) -> Vec<BackendSources> {
+    let mut backends: HashMap<u64, BackendSources> = HashMap::new();
+
+    for ((backend_id, id), r) in sources {
+        backends.entry(backend_id)
+            .or_insert_with(|| (Arc::clone(&r.backend), HashMap::new()))
+            .1.insert(id, r);
+    }
+
+    backends.into_values().collect()
+}
+
+// returns how the registered audio sources of the backend changed, which only includes
+// audio sources whose update was written
+fn apply(backend: &Arc<dyn AudioBackend>, registered: &HashMap<u64, Registered>, amount: f64) -> audio::AudioResult<Vec<(u64, Change)>> {
+    let status = audio::parse_status(backend)?;
+    let sources = status["Sources"].members()
+        .map(|s| (s["ID"].as_u64().unwrap_or(0), s["Paused"].as_bool().unwrap_or(false)))
+        .collect::<Vec<_>>();
+
+    // forget audio sources that are gone
+    let present = sources.iter().map(|&(id, _)| id).collect::<HashSet<_>>();
+    let mut changes = registered.keys()
+        .filter(|id| !present.contains(id))
+        .map(|&id| (id, Change::Gone))
+        .collect::<Vec<_>>();
+
+    let priorities = registered.iter().map(|(&id, r)| (id, r.priority)).collect();
+    let should_duck = sources_to_duck(&sources, &priorities);
+
+    for s in status["Sources"].members() {
+        let id = s["ID"].as_u64().unwrap_or(0);
+        let volume = s["Volume"].as_f64().unwrap_or(1.0);
+
+        let (new_volume, ducked) = match (should_duck.contains(&id), registered.get(&id).and_then(|r| r.ducked)) {
+            (true, None) => (volume * (1.0 - amount), Some(volume)),
+            (false, Some(prev_volume)) => (prev_volume, None),
+            _ => continue
+        };
+
+        match audio::update_source(backend, id, &update_from_status(s, new_volume)) {
+            Ok(()) => changes.push((id, Change::Ducked(ducked))),
+            Err(e) => log::debug!("Error in ducking audio source {}. ({})", id, e)
+        }
+    }
+
+    Ok(changes)
+}
+
+fn restore_all() {
+    let sources = {
+        let mut state = DUCK_STATE.lock().unwrap();
+        let sources = state.sources.clone();
+
+        for r in state.sources.values_mut() {
+            r.ducked = None;
+        }
+
+        sources
+    };
+
+    for (backend, sources) in by_backend(sources) {
+        if sources.values().all(|r| r.ducked.is_none()) {
+            continue;
+        }
+
+        let status = match audio::parse_status(&backend) {
+            Ok(s) => s,
+            Err(e) => {
+                log::debug!("Error in restoring ducked audio sources. ({})", e);
+                continue;
+            }
+        };
+
+        for (id, r) in sources {
+            let volume = match r.ducked {
+                Some(v) => v,
+                None => continue
+            };
+
+            if let Some(s) = status["Sources"].members().find(|s| s["ID"] == id) {
+                if let Err(e) = audio::update_source(&backend, id, &update_from_status(s, volume)) {
+                    log::debug!("Error in restoring ducked audio source {}. ({})", id, e);
+                }
+            }
+        }
+    }
+}
+
+fn update_from_status(status: &json::JsonValue, volume: f64) -> AudioUpdate {
+    let loop_count = status["Loop"].as_i64().unwrap_or(0);
+
+    AudioUpdate {
+        volume,
+        pan: status["Pan"].as_f64().unwrap_or(0.0),
+        paused: status["Paused"].as_bool().unwrap_or(false),
+        does_loop: loop_count != 0,
+        loop_count
+    }
+}
+
+// registered audio sources with a lower priority than the highest priority registered audio
+// source that is playing should be ducked, and other audio sources are ignored
+fn sources_to_duck(sources: &[(u64, bool)], priorities: &HashMap<u64, u8>) -> HashSet<u64> {
+    let registered = sources.iter().filter_map(|&(id, paused)| priorities.get(&id).map(|&p| (id, p, paused))).collect::<Vec<_>>();
+    let top = registered.iter().filter(|(_, _, paused)| !paused).map(|&(_, p, _)| p).max();
+
+    match top {
+        Some(top) => registered.iter().filter(|&&(_, p, _)| p < top).map(|&(id, _, _)| id).collect(),
+        None => HashSet::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::{source_status, status_with, MockBackend};
+
+    #[test]
+    fn test_sources_to_duck() {
+        let priorities = [(1, 0), (2, 5), (3, 1)].iter().copied().collect::<HashMap<_, _>>();
+
+        let sources = [(1, false), (2, false), (3, false)];
+        assert_eq!(sources_to_duck(&sources, &priorities), [1, 3].iter().copied().collect());
+
+        // paused audio sources do not cause ducking
+        let sources = [(1, false), (2, true), (3, false)];
+        assert_eq!(sources_to_duck(&sources, &priorities), [1].iter().copied().collect());
+
+        // audio sources that were not registered are never ducked
+        let sources = [(2, false), (4, false)];
+        assert!(sources_to_duck(&sources, &priorities).is_empty());
+
+        let sources = [(1, false), (4, false)];
+        assert!(sources_to_duck(&sources, &priorities).is_empty());
+        assert!(sources_to_duck(&[], &priorities).is_empty());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mock = Arc::new(MockBackend::with_status(&status_with(vec![
+            source_status("rust_audio_test_duck_low", 1, 0.8),
+            source_status("rust_audio_test_duck_high", 2, 1.0),
+            source_status("rust_audio_test_duck_other", 4, 1.0)
+        ])));
+        let backend = Arc::clone(&mock) as Arc<dyn AudioBackend>;
+        let registered = [(1, 0), (2, 5), (7, 0)].iter()
+            .map(|&(id, priority)| (id, Registered { backend: Arc::clone(&backend), priority, ducked: None }))
+            .collect::<HashMap<_, _>>();
+
+        let mut changes = apply(&backend, &registered, 0.5).unwrap();
+        changes.sort_by_key(|&(id, _)| id);
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(changes[0], (1, Change::Ducked(Some(v))) if v == 0.8));
+        assert!(matches!(changes[1], (7, Change::Gone)));
+
+        // only the registered lower priority audio source is updated, and not the audio
+        // source of another program
+        let commands = mock.commands();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0]["ID"], 1);
+        assert_eq!(commands[0]["Volume"], 0.4);
+    }
+}
@@ -0,0 +1,160 @@
+//! Looping with a pause between iterations, which the native looping cannot do.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::audio::{self, Audio, AudioBuilder, AudioResult};
+use crate::clock;
+
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A handle to an audio instance that is looped with a gap by `AudioBuilder::loop_with_gap`.
+///
+/// Dropping the handle or calling `stop` stops the audio instance that is playing and the
+/// background thread.
+pub struct GapLoop {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>
+}
+
+impl AudioBuilder {
+    /// Play the audio instance, then play it again `count` more times, with a pause of `gap`
+    /// after each time it finishes. A negative `count` loops forever.
+    ///
+    /// Like with `loop_count`, a `count` of `0` plays the audio instance once. A background
+    /// thread waits for each audio instance to finish before starting the next one, and the
+    /// loop settings of the builder are ignored. This blocks until the first audio instance
+    /// begins playing. Errors in playing later audio instances stop the loop, and are logged
+    /// through the `log` crate.
+    pub fn loop_with_gap(&self, count: i64, gap: Duration) -> AudioResult<GapLoop> {
+        let builder = self.clone().does_loop(false).loop_count(0);
+        let first = builder.build()?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+
+        // a dedicated thread, since the loop may run until it is stopped
+        let handle = thread::spawn(move || {
+            let mut current = first;
+
+            if let Err(e) = run(&builder, count, gap, &thread_stop, &mut current) {
+                log::warn!("Stopped loop with gap. ({})", e);
+            }
+
+            // the last audio instance may still be playing if the loop was stopped
+            if thread_stop.load(Ordering::SeqCst) {
+                let _ = audio::write_stop(&builder.backend, current.get_id());
+            }
+        });
+
+        Ok(GapLoop { stop, handle: Some(handle) })
+    }
+}
+
+fn run(builder: &AudioBuilder, count: i64, gap: Duration, stop: &AtomicBool, current: &mut Audio) -> AudioResult<()> {
+    let mut played = 0;
+
+    while count < 0 || played < count {
+        if !current.wait_until_finished_or_cancelled(stop)? {
+            return Ok(());
+        }
+
+        // sleep in short steps, so stopping does not wait for the whole gap
+        let start = clock::now();
+
+        loop {
+            if stop.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+
+            let elapsed = clock::elapsed(start);
+
+            if elapsed >= gap {
+                break;
+            }
+
+            clock::sleep((gap - elapsed).min(STOP_POLL_INTERVAL));
+        }
+
+        *current = builder.build()?;
+        played += 1;
+    }
+
+    Ok(())
+}
+
+impl GapLoop {
+    /// Stop the loop and the audio instance that is playing.
+    pub fn stop(self) {
+        // dropping does the work
+    }
+
+    /// Get whether the loop has played the audio instance as many times as it should, or
+    /// stopped because of an error.
+    ///
+    /// The last audio instance may still be playing.
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().is_none_or(|h| h.is_finished())
+    }
+}
+
+impl Drop for GapLoop {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Instant;
+
+    use crate::audio::ToneType;
+    use crate::backend::{single_read_status, AudioBackend, MockBackend};
+
+    #[test]
+    fn test_loop_with_gap() {
+        // each audio source finishes right after it begins playing
+        let backend = Arc::new(MockBackend::new());
+        backend.set_status_fn(single_read_status(|_| true));
+        let builder = AudioBuilder::tone(ToneType::Sine, 440.0, 0.05)
+            .does_loop(true)
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>);
+        let plays = || backend.commands().iter().filter(|c| c["Name"].is_string()).count();
+
+        let _lock = clock::TEST_LOCK.lock().unwrap();
+        let start = Instant::now();
+        let gap_loop = builder.loop_with_gap(2, Duration::from_millis(30)).unwrap();
+
+        while !gap_loop.is_finished() && start.elapsed() < Duration::from_secs(1) {
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        assert!(gap_loop.is_finished());
+        assert_eq!(plays(), 3);
+        assert!(start.elapsed() >= Duration::from_millis(60));
+        // the loop settings of the builder are ignored
+        assert!(backend.commands().iter().filter(|c| c["Name"].is_string()).all(|c| c["DoesLoop"] == false));
+        gap_loop.stop();
+
+        // looping forever until it is stopped
+        backend.clear_commands();
+        let gap_loop = builder.loop_with_gap(-1, Duration::from_millis(10)).unwrap();
+        thread::sleep(Duration::from_millis(100));
+        assert!(!gap_loop.is_finished());
+        gap_loop.stop();
+
+        let count = plays();
+        assert!(count >= 2, "{} plays", count);
+        assert_eq!(backend.commands().last().unwrap()["Stop"], true);
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(plays(), count);
+    }
+}
@@ -0,0 +1,104 @@
+//! Controlling several audio instances together, like background music and sound effects.
+
+use crate::audio::{self, Audio, AudioError, AudioResult};
+
+/// A group of audio instances that can be paused, resumed, updated, and stopped together.
+///
+/// Each operation is applied to every audio instance in the group, even if it fails for
+/// some of them, and the returned error lists all of the failures.
+#[derive(Clone, Default)]
+pub struct AudioGroup {
+    pub audios: Vec<Audio>
+}
+
+impl AudioGroup {
+    /// Create a new `AudioGroup` with the audio instances in `audios`.
+    pub fn new(audios: Vec<Audio>) -> Self {
+        AudioGroup { audios }
+    }
+
+    /// Add an audio instance to the group.
+    pub fn push(&mut self, audio: Audio) {
+        self.audios.push(audio);
+    }
+
+    /// Pause every audio instance in the group, like with `Audio::pause`.
+    pub fn pause_all(&mut self) -> AudioResult<()> {
+        self.for_each("pausing", Audio::pause)
+    }
+
+    /// Resume every audio instance in the group, like with `Audio::resume`.
+    pub fn resume_all(&mut self) -> AudioResult<()> {
+        self.for_each("resuming", Audio::resume)
+    }
+
+    /// Set the volume of every audio instance in the group, like with `Audio::set_volume`.
+    ///
+    /// If the volume is not between `0.0` and `1.0`, then an error is returned without
+    /// updating any audio instance.
+    pub fn set_volume_all(&mut self, volume: f64) -> AudioResult<()> {
+        audio::check_volume(volume)?;
+        self.for_each("setting the volume of", |a| a.set_volume(volume))
+    }
+
+    /// Stop every audio instance in the group, like with `Audio::stop_nowait`.
+    ///
+    /// Audio instances that have already finished are not an error, since the stop command
+    /// is ignored for them. The audio instances are kept in the group.
+    pub fn stop_all(&mut self) -> AudioResult<()> {
+        self.for_each("stopping", Audio::stop_nowait)
+    }
+
+    fn for_each<F: FnMut(&mut Audio) -> AudioResult<()>>(&mut self, action: &str, mut f: F) -> AudioResult<()> {
+        let errors = self.audios.iter_mut()
+            .filter_map(|a| f(a).err().map(|e| format!("{}: {}", a.get_id(), e)))
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(AudioError::new(format!("Error in {} audio sources. ({})", action, errors.join("; "))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use crate::audio::{AudioBuilder, AudioType, ToneType};
+    use crate::backend::{source_status, status_with, AudioBackend, MockBackend};
+
+    #[test]
+    fn test_group() {
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![
+            source_status("rust_audio_test_group_a", 1, 0.5),
+            source_status("rust_audio_test_group_b", 2, 0.5)
+        ])));
+        let builder = AudioBuilder::new(&AudioType::Tone { tone: ToneType::Sine, pitch: 440.0, duration: 1.0 })
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>);
+
+        let first = builder.clone().name("rust_audio_test_group_a").build().unwrap();
+        let mut group = AudioGroup::new(vec![first]);
+        group.push(builder.name("rust_audio_test_group_b").build().unwrap());
+        backend.clear_commands();
+
+        group.pause_all().unwrap();
+        assert!(group.set_volume_all(2.0).is_err());
+        assert_eq!(backend.commands().len(), 2);
+
+        // the second audio instance finished, but the first one is still updated
+        backend.set_status(&status_with(vec![source_status("rust_audio_test_group_a", 1, 0.5)]));
+        let err = group.set_volume_all(0.25).unwrap_err().to_string();
+        assert!(err.starts_with("Error in setting the volume of audio sources. (2: "));
+
+        group.stop_all().unwrap();
+        let commands = backend.commands();
+        assert_eq!(commands.len(), 5);
+        assert_eq!(commands[2]["Volume"], 0.25);
+        assert_eq!(commands[3]["Stop"], true);
+        assert_eq!(commands[4]["ID"], 2);
+    }
+}
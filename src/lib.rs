@@ -5,6 +5,38 @@
 //! with the `Audio` struct.
 
 pub mod audio;
+pub mod music;
+mod backend;
+mod clock;
+#[cfg(feature = "download")]
+mod download;
+mod duck;
+mod gap;
+mod group;
+mod metronome;
+mod sandbox;
+mod replay;
+mod seamless;
+mod tasks;
+mod timer;
+mod wav;
+mod worker;
+mod writer;
 
 // re-export the functions and structs in the audio file
 pub use audio::*;
+pub use music::*;
+pub use backend::{AudioBackend, ReplitBackend};
+pub use duck::set_auto_duck;
+pub use gap::GapLoop;
+pub use group::AudioGroup;
+pub use metronome::Metronome;
+pub use replay::{clear_command_log, replay_command_log, set_command_log};
+pub use sandbox::set_sandbox_mode;
+pub use seamless::SeamlessLoop;
+pub use wav::wav_duration;
+pub use worker::set_worker_threads;
+#[cfg(feature = "mock-clock")]
+pub use clock::MockClock;
+#[cfg(feature = "mock-backend")]
+pub use backend::MockBackend;
@@ -0,0 +1,281 @@
+//! A metronome that plays a short click on every beat.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::audio::{Audio, AudioBuilder, AudioError, AudioResult, ToneType};
+use crate::clock;
+
+const CLICK_PITCH: f64 = 1000.0;
+// in seconds
+const CLICK_DURATION: f64 = 0.05;
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A metronome that plays a short tone on every beat in a background thread.
+///
+/// Dropping the metronome stops it like `stop`.
+pub struct Metronome {
+    bpm: Arc<Mutex<f64>>,
+    builder: AudioBuilder,
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>
+}
+
+impl Metronome {
+    /// Create a new `Metronome` with a tempo in beats per minute, which clicks with a short
+    /// tone of a certain `ToneType`.
+    ///
+    /// The metronome does not click until it is started with `start`.
+    pub fn new(bpm: f64, tone: ToneType) -> Self {
+        Metronome {
+            bpm: Arc::new(Mutex::new(bpm)),
+            builder: AudioBuilder::tone(tone, CLICK_PITCH, CLICK_DURATION),
+            stop: Arc::new(AtomicBool::new(false)),
+            handle: None
+        }
+    }
+
+    /// Start clicking, with the first click right away.
+    ///
+    /// This blocks until the first click begins playing, and returns an error if it fails to
+    /// begin playing or if the tempo is not positive. The beats are scheduled from the start
+    /// time of the first click in the status file, and each later click is written early by
+    /// how long the repl.it audio playing program took to start the click before it, as
+    /// reported by its start time in the status file. This keeps the clicks on the beats even
+    /// when starting a click is slow. Errors in playing later clicks are logged through the
+    /// `log` crate, and the metronome keeps going. This does nothing if the metronome is already running.
+    pub fn start(&mut self) -> AudioResult<()> {
+        if self.handle.is_some() {
+            return Ok(());
+        }
+
+        check_bpm(*self.bpm.lock().unwrap())?;
+        let written = clock::utc_now();
+        let first = self.builder.build()?;
+
+        let bpm = Arc::clone(&self.bpm);
+        let builder = self.builder.clone();
+        self.stop = Arc::new(AtomicBool::new(false));
+        let stop = Arc::clone(&self.stop);
+
+        // a dedicated thread, since clicking runs until it is stopped
+        self.handle = Some(thread::spawn(move || {
+            let mut last = run(&builder, &bpm, &stop, first, written);
+
+            // the last click may still be playing
+            if let Err(e) = last.stop_nowait() {
+                log::debug!("Error in stopping the last click of a metronome. ({})", e);
+            }
+        }));
+
+        Ok(())
+    }
+
+    /// Stop clicking, and block until the background thread exits and the click that is
+    /// playing is stopped.
+    ///
+    /// This does nothing if the metronome is not running. The metronome can be started again
+    /// afterwards.
+    pub fn stop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+
+    /// Change the tempo, in beats per minute, which also works while the metronome is
+    /// running.
+    ///
+    /// The new tempo is used starting from the next beat. An error is returned if the tempo
+    /// is not positive.
+    pub fn set_bpm(&self, bpm: f64) -> AudioResult<()> {
+        check_bpm(bpm)?;
+        *self.bpm.lock().unwrap() = bpm;
+        Ok(())
+    }
+
+    /// Get the tempo in beats per minute.
+    pub fn get_bpm(&self) -> f64 {
+        *self.bpm.lock().unwrap()
+    }
+
+    /// Get whether the metronome is running.
+    pub fn is_running(&self) -> bool {
+        self.handle.is_some()
+    }
+}
+
+impl Drop for Metronome {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// returns the last click that was played, where `written` is when the first click was written
+fn run(builder: &AudioBuilder, bpm: &Mutex<f64>, stop: &AtomicBool, first: Audio, written: DateTime<Utc>) -> Audio {
+    // the latency is zero if the status of the first click cannot be read, like when it
+    // already finished
+    let mut latency = first.get_start_time_utc().ok()
+        .and_then(|start| self::latency(start, written, beat_interval(bpm)))
+        .unwrap_or_else(chrono::Duration::zero);
+    // the first beat is when the first click began playing
+    let mut beat = written + latency;
+    let mut last = first;
+
+    loop {
+        let interval = beat_interval(bpm);
+        beat += interval;
+        let write_at = beat - latency;
+
+        // sleep in short steps, so stopping does not wait for the next beat
+        loop {
+            if stop.load(Ordering::SeqCst) {
+                return last;
+            }
+
+            let now = clock::utc_now();
+
+            if now >= write_at {
+                break;
+            }
+
+            let left = (write_at - now).to_std().unwrap_or(Duration::ZERO);
+            clock::sleep(left.min(STOP_POLL_INTERVAL));
+        }
+
+        let written = clock::utc_now();
+
+        match builder.build() {
+            Ok(audio) => {
+                // keep the previous latency if the start time cannot be read or is off
+                if let Some(l) = audio.get_start_time_utc().ok().and_then(|start| self::latency(start, written, interval)) {
+                    latency = l;
+                }
+
+                last = audio;
+            },
+            Err(e) => log::warn!("Error in playing a metronome click. ({})", e)
+        }
+    }
+}
+
+fn beat_interval(bpm: &Mutex<f64>) -> chrono::Duration {
+    chrono::Duration::from_std(Duration::from_secs_f64(60.0 / *bpm.lock().unwrap())).unwrap_or_else(|_| chrono::Duration::zero())
+}
+
+// how long the audio playing program took to start a click after it was written, where a
+// start time that is more than a beat away from when the click was written is not trusted,
+// since then the clicks would be scheduled far from the beats
+fn latency(start: DateTime<Utc>, written: DateTime<Utc>, interval: chrono::Duration) -> Option<chrono::Duration> {
+    let latency = start - written;
+
+    if latency.abs() > interval {
+        None
+    } else {
+        // the start time can be slightly early, since it may be rounded
+        Some(latency.max(chrono::Duration::zero()))
+    }
+}
+
+fn check_bpm(bpm: f64) -> AudioResult<()> {
+    if bpm > 0.0 && bpm.is_finite() {
+        Ok(())
+    } else {
+        Err(AudioError::new(format!("The tempo {} BPM is invalid.", bpm)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use chrono::SecondsFormat;
+
+    use crate::backend::{playing_status, source_status, status_with, AudioBackend, MockBackend};
+
+    fn clicks(commands: &[json::JsonValue]) -> usize {
+        commands.iter().filter(|c| c["Name"].is_string()).count()
+    }
+
+    #[test]
+    fn test_metronome_schedule() {
+        let stop = Arc::new(AtomicBool::new(false));
+        let backend = Arc::new(MockBackend::new());
+        let status_stop = Arc::clone(&stop);
+        // when each click was written, which is when the status is read next
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let status_written = Arc::clone(&written);
+        // the first click begins playing 20 ms after it is written, and later clicks 10 ms
+        backend.set_status_fn(move |commands| {
+            let mut written = status_written.lock().unwrap();
+            let names = commands.iter().filter_map(|c| c["Name"].as_str()).collect::<Vec<_>>();
+            let n = names.len();
+
+            while written.len() < n {
+                written.push(clock::utc_now());
+            }
+
+            // stop once the fourth click is played, so it is the last one
+            if n >= 4 {
+                status_stop.store(true, Ordering::SeqCst);
+            }
+
+            status_with(names.iter().zip(written.iter()).enumerate().map(|(i, (name, &t))| {
+                let mut source = source_status(name, i as u64 + 1, 1.0);
+                let latency = chrono::Duration::milliseconds(if i == 0 { 20 } else { 10 });
+                source["StartTime"] = (t + latency).to_rfc3339_opts(SecondsFormat::Millis, true).into();
+                source
+            }).collect())
+        });
+        let builder = AudioBuilder::tone(ToneType::Sine, CLICK_PITCH, CLICK_DURATION)
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>);
+        // 50 ms per beat
+        let bpm = Mutex::new(1200.0);
+
+        let _lock = clock::TEST_LOCK.lock().unwrap();
+        clock::MockClock::install();
+        let first_written = clock::utc_now();
+        let first = builder.build().unwrap();
+        let last = run(&builder, &bpm, &stop, first, first_written);
+        clock::MockClock::uninstall();
+
+        assert_eq!(last.get_id(), 4);
+        assert_eq!(clicks(&backend.commands()), 4);
+        // the beats are 50 ms apart from when the first click began playing, at 20 ms, and
+        // each click is written early by the latency of the click before it
+        let offsets = written.lock().unwrap().iter()
+            .map(|&t| (t - first_written).num_milliseconds())
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 50, 110, 160]);
+    }
+
+    #[test]
+    fn test_metronome() {
+        let backend = Arc::new(MockBackend::new());
+        backend.set_status_fn(playing_status(|_| true));
+        let mut metronome = Metronome::new(0.0, ToneType::Sine);
+        metronome.builder.backend = Arc::clone(&backend) as Arc<dyn AudioBackend>;
+
+        assert!(metronome.start().is_err());
+        assert!(!metronome.is_running());
+        assert!(metronome.set_bpm(-60.0).is_err());
+        metronome.set_bpm(1200.0).unwrap();
+
+        let _lock = clock::TEST_LOCK.lock().unwrap();
+        metronome.start().unwrap();
+        assert!(metronome.is_running());
+        metronome.stop();
+        assert!(!metronome.is_running());
+
+        // the first click is played before start returns
+        let commands = backend.commands();
+        assert!(clicks(&commands) >= 1);
+        assert_eq!(commands[0]["Args"]["Pitch"], CLICK_PITCH);
+        assert_eq!(commands.last().unwrap()["Stop"], true);
+    }
+}
@@ -0,0 +1,303 @@
+//! Helpers for playing music with tones.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::audio::{self, Audio, AudioBuilder, AudioError, AudioResult, AudioType, ToneType};
+use crate::backend::{self, AudioBackend};
+
+const A4_PITCH: f64 = 440.0;
+const A4_OCTAVE: i32 = 4;
+
+/// Musical scales that can be played with `play_scale`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Scale {
+    Major,
+    /// The natural minor scale.
+    Minor,
+    Chromatic,
+    /// The major pentatonic scale.
+    Pentatonic
+}
+
+impl Scale {
+    // offsets from the root note in semitones, including the octave
+    fn offsets(&self) -> &'static [i32] {
+        match self {
+            Scale::Major => &[0, 2, 4, 5, 7, 9, 11, 12],
+            Scale::Minor => &[0, 2, 3, 5, 7, 8, 10, 12],
+            Scale::Chromatic => &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12],
+            Scale::Pentatonic => &[0, 2, 4, 7, 9, 12]
+        }
+    }
+}
+
+impl AudioType {
+    /// Create an `AudioType::Tone` for a note, with the equal-tempered pitch relative to A4
+    /// at 440 Hz.
+    ///
+    /// The `note` is a note name without an octave, like `"A"`, `"C#"`, or `"Bb"`, with
+    /// `#` for sharps and `b` for flats. An error is returned if the note name is malformed.
+    pub fn tone_from_note(tone: ToneType, note: &str, octave: i32, duration: f64) -> AudioResult<AudioType> {
+        let pitch = semitones_to_pitch(semitones_from_a4(note, octave)?);
+        Ok(AudioType::Tone { tone, pitch, duration })
+    }
+}
+
+/// Equal-tempered pitches in Hz of the notes from C4 to C5, relative to A4 at 440 Hz, like
+/// in `AudioType::tone_from_note`.
+///
+/// Sharps are named with an `S`, like `CS4` for C#4.
+pub mod pitches {
+    /// C4.
+    pub const C4: f64 = 261.6255653005986;
+    /// C#4.
+    pub const CS4: f64 = 277.1826309768721;
+    /// D4.
+    pub const D4: f64 = 293.6647679174076;
+    /// D#4.
+    pub const DS4: f64 = 311.1269837220809;
+    /// E4.
+    pub const E4: f64 = 329.6275569128699;
+    /// F4.
+    pub const F4: f64 = 349.2282314330039;
+    /// F#4.
+    pub const FS4: f64 = 369.9944227116344;
+    /// G4.
+    pub const G4: f64 = 391.99543598174927;
+    /// G#4.
+    pub const GS4: f64 = 415.3046975799451;
+    /// A4.
+    pub const A4: f64 = 440.0;
+    /// A#4.
+    pub const AS4: f64 = 466.1637615180899;
+    /// B4.
+    pub const B4: f64 = 493.8833012561241;
+    /// C5.
+    pub const C5: f64 = 523.2511306011972;
+}
+
+/// Play a scale up and then back down, starting from the `root` note, with each note
+/// lasting `note_duration` seconds.
+///
+/// The `root` note is a note name with an octave, like `"C4"`, `"F#3"`, or `"Bb5"`. The
+/// top note is only played once. This blocks until the whole scale has been played.
+pub fn play_scale(root: &str, scale: Scale, tone: ToneType, note_duration: f64) -> AudioResult<()> {
+    play_scale_on(&backend::default_backend(), root, scale, tone, note_duration)
+}
+
+fn play_scale_on(backend: &Arc<dyn AudioBackend>, root: &str, scale: Scale, tone: ToneType, note_duration: f64) -> AudioResult<()> {
+    let root = parse_note(root)?;
+    let offsets = scale.offsets();
+
+    let notes = offsets.iter()
+        .chain(offsets.iter().rev().skip(1))
+        .map(|offset| AudioType::Tone { tone, pitch: semitones_to_pitch(root + offset), duration: note_duration })
+        .collect::<Vec<_>>();
+
+    audio::play_sequence_on(backend, &notes, Duration::ZERO)
+}
+
+/// Play several tones at the same time as a chord, with one audio instance for each tone
+/// type and pitch (in Hz) in `tones`, and return the audio instances.
+///
+/// All of the tones last `duration` seconds and play at the same `volume`. This blocks until
+/// every tone begins playing. If a tone fails to begin playing, then the tones that already
+/// began playing are stopped and the error is returned.
+pub fn play_chord(tones: &[(ToneType, f64)], duration: f64, volume: f64) -> AudioResult<Vec<Audio>> {
+    match tones.first() {
+        Some(&(tone, pitch)) => {
+            let builder = AudioBuilder::tone(tone, pitch, duration).volume(volume);
+            play_chord_with(&builder, tones, duration)
+        },
+        None => Ok(Vec::new())
+    }
+}
+
+// play the chord with the other settings of the builder
+fn play_chord_with(builder: &AudioBuilder, tones: &[(ToneType, f64)], duration: f64) -> AudioResult<Vec<Audio>> {
+    let mut audios = Vec::with_capacity(tones.len());
+
+    for &(tone, pitch) in tones {
+        match builder.with_type(&AudioType::Tone { tone, pitch, duration }).build() {
+            Ok(audio) => audios.push(audio),
+            Err(e) => {
+                for mut audio in audios {
+                    if let Err(stop_error) = audio.stop_nowait() {
+                        log::warn!("Error in stopping audio source {} of a chord. ({})", audio.get_id(), stop_error);
+                    }
+                }
+
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(audios)
+}
+
+// parse a note name with an octave, like "C#5", into the number of semitones from A4
+fn parse_note(note: &str) -> AudioResult<i32> {
+    let octave_start = note.find(|c: char| c.is_ascii_digit() || c == '-')
+        .ok_or_else(|| AudioError::new(format!("Missing octave in note {}.", note)))?;
+    let octave = note[octave_start..].parse::<i32>()
+        .map_err(|_| AudioError::new(format!("Invalid octave in note {}.", note)))?;
+
+    semitones_from_a4(&note[..octave_start], octave)
+}
+
+// get the number of semitones from A4 to a note name without an octave, like "Bb", in
+// the given octave
+fn semitones_from_a4(name: &str, octave: i32) -> AudioResult<i32> {
+    let mut chars = name.chars();
+
+    // semitones from C in the same octave
+    let base = match chars.next().map(|c| c.to_ascii_uppercase()) {
+        Some('C') => 0,
+        Some('D') => 2,
+        Some('E') => 4,
+        Some('F') => 5,
+        Some('G') => 7,
+        Some('A') => 9,
+        Some('B') => 11,
+        _ => return Err(AudioError::new(format!("Invalid note name {}.", name)))
+    };
+
+    let accidental = match chars.as_str() {
+        "" => 0,
+        "#" => 1,
+        "b" => -1,
+        _ => return Err(AudioError::new(format!("Invalid note name {}.", name)))
+    };
+
+    Ok((octave - A4_OCTAVE) * 12 + base + accidental - 9)
+}
+
+// get the equal-tempered pitch some number of semitones away from A4
+fn semitones_to_pitch(semitones: i32) -> f64 {
+    A4_PITCH * 2f64.powf(semitones as f64 / 12.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::{playing_status, single_read_status, MockBackend};
+    use crate::clock;
+
+    #[test]
+    fn test_pitches() {
+        let pitches = [
+            pitches::C4, pitches::CS4, pitches::D4, pitches::DS4, pitches::E4, pitches::F4,
+            pitches::FS4, pitches::G4, pitches::GS4, pitches::A4, pitches::AS4, pitches::B4, pitches::C5
+        ];
+
+        for (i, pitch) in pitches.iter().enumerate() {
+            assert!((pitch - semitones_to_pitch(i as i32 - 9)).abs() < 1e-9, "{} {}", i, pitch);
+        }
+
+        assert_eq!(pitches::A4, 440.0);
+        assert!((pitches::C5 - 2.0 * pitches::C4).abs() < 1e-9);
+        assert_eq!(pitches::E4, semitones_to_pitch(parse_note("E4").unwrap()));
+    }
+
+    #[test]
+    fn test_play_chord() {
+        let backend = Arc::new(MockBackend::new());
+        // tones with a pitch of 660 Hz never begin playing
+        backend.set_status_fn(playing_status(|c| c["Args"]["Pitch"] != 660.0));
+        let builder = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+            .volume(0.5)
+            .backend(Arc::clone(&backend) as Arc<dyn AudioBackend>)
+            .timeout(Duration::from_millis(50));
+
+        let chord = [(ToneType::Sine, 440.0), (ToneType::Sine, 550.0)];
+        let audios = play_chord_with(&builder, &chord, 1.0).unwrap();
+        assert_eq!(audios.iter().map(|a| a.get_id()).collect::<Vec<_>>(), vec![1, 2]);
+        assert!(play_chord(&[], 1.0, 0.5).unwrap().is_empty());
+
+        // the tones that began playing are stopped once a tone fails to begin playing
+        let chord = [(ToneType::Square, 330.0), (ToneType::Triangle, 660.0), (ToneType::Sine, 880.0)];
+        assert!(play_chord_with(&builder, &chord, 1.0).is_err());
+
+        let commands = backend.commands()[2..].to_vec();
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[0]["Volume"], 0.5);
+        assert_eq!(commands[1]["Args"]["Pitch"], 660.0);
+        assert_eq!(commands[2]["ID"], 3);
+        assert_eq!(commands[2]["Stop"], true);
+    }
+
+    #[test]
+    fn test_parse_note() {
+        assert_eq!(parse_note("A4").unwrap(), 0);
+        assert_eq!(parse_note("C4").unwrap(), -9);
+        assert_eq!(parse_note("C#5").unwrap(), 4);
+        assert_eq!(parse_note("Bb3").unwrap(), -11);
+        assert_eq!(parse_note("A-1").unwrap(), -60);
+
+        assert!(parse_note("H4").is_err());
+        assert!(parse_note("A").is_err());
+        assert!(parse_note("A#b4").is_err());
+        assert!(parse_note("").is_err());
+    }
+
+    #[test]
+    fn test_tone_from_note() {
+        let notes = [
+            ("A", 4, 440.0),
+            ("A", 3, 220.0),
+            ("C", 4, 261.626),
+            ("C#", 5, 554.365),
+            ("Db", 5, 554.365),
+            ("Bb", 3, 233.082),
+            ("E", 2, 82.407),
+            ("G#", 6, 1661.219)
+        ];
+
+        for &(note, octave, expected) in &notes {
+            match AudioType::tone_from_note(ToneType::Sine, note, octave, 0.5).unwrap() {
+                AudioType::Tone { pitch, duration, .. } => {
+                    assert!((pitch - expected).abs() < 0.001, "{}{} is {} Hz", note, octave, pitch);
+                    assert_eq!(duration, 0.5);
+                },
+                _ => panic!("expected a tone")
+            }
+        }
+
+        assert!(AudioType::tone_from_note(ToneType::Sine, "H", 4, 0.5).is_err());
+        assert!(AudioType::tone_from_note(ToneType::Sine, "C##", 4, 0.5).is_err());
+        assert!(AudioType::tone_from_note(ToneType::Sine, "", 4, 0.5).is_err());
+    }
+
+    #[test]
+    fn test_play_scale() {
+        let backend = Arc::new(MockBackend::new());
+        // each note finishes right after it begins playing
+        backend.set_status_fn(single_read_status(|_| true));
+
+        let _lock = clock::TEST_LOCK.lock().unwrap();
+        clock::MockClock::install();
+        let result = play_scale_on(&(Arc::clone(&backend) as Arc<dyn AudioBackend>), "C4", Scale::Major, ToneType::Square, 0.25);
+        clock::MockClock::uninstall();
+        result.unwrap();
+
+        // up and back down, without repeating the top note
+        let expected = [
+            pitches::C4, pitches::D4, pitches::E4, pitches::F4, pitches::G4, pitches::A4, pitches::B4,
+            pitches::C5, pitches::B4, pitches::A4, pitches::G4, pitches::F4, pitches::E4, pitches::D4, pitches::C4
+        ];
+        let commands = backend.commands();
+        assert_eq!(commands.len(), expected.len());
+
+        for (command, pitch) in commands.iter().zip(expected.iter()) {
+            assert!((command["Args"]["Pitch"].as_f64().unwrap() - pitch).abs() < 1e-9, "{} {}", command, pitch);
+            assert_eq!(command["Args"]["Seconds"], 0.25);
+        }
+    }
+
+    #[test]
+    fn test_play_scale_invalid_root() {
+        assert!(play_scale("X4", Scale::Major, ToneType::Sine, 0.25).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Logging every command written to the repl.it audio playing program, and replaying the
+//! log later, for reproducing timing-sensitive issues.
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use lazy_static::lazy_static;
+use json::{self, object};
+use chrono::DateTime;
+
+use crate::audio::{self, AudioError, AudioResult};
+use crate::backend::{self, AudioBackend};
+use crate::{clock, writer};
+
+const REPLAY_START_TIMEOUT: Duration = Duration::from_secs(2);
+
+lazy_static! {
+    static ref COMMAND_LOG: Mutex<Option<String>> = Mutex::new(None);
+}
+
+/// Append every command that is written to the repl.it audio playing program to the file at
+/// `path`, along with the time it was written, so it can be replayed with
+/// `replay_command_log`.
+///
+/// The file is created if it does not exist. Each line of the file is a JSON object with
+/// the time and either the command or the ID that the audio playing program gave to a
+/// newly played audio instance. Errors in writing to the log are logged through the `log`
+/// crate instead of failing the command.
+pub fn set_command_log(path: &str) -> AudioResult<()> {
+    fs::OpenOptions::new().create(true).append(true).open(path)?;
+    *COMMAND_LOG.lock().unwrap() = Some(path.to_owned());
+    Ok(())
+}
+
+/// Stop appending commands to the file set with `set_command_log`.
+pub fn clear_command_log() {
+    *COMMAND_LOG.lock().unwrap() = None;
+}
+
+/// Write all commands from a log created with `set_command_log` to the repl.it audio
+/// playing program again, with the time between commands divided by `speed`.
+///
+/// The IDs in update commands are replaced with the IDs of the replayed audio instances.
+/// File paths in the log are not changed, so they must still be valid. This blocks until
+/// all commands have been written. If a command log is set, the replayed commands are also
+/// appended to it. The commands are always written to `ReplitBackend`, even if they were
+/// logged from audio instances with a different backend.
+pub fn replay_command_log(path: &str, speed: f64) -> AudioResult<()> {
+    replay_on(&backend::default_backend(), path, speed)
+}
+
+fn replay_on(backend: &Arc<dyn AudioBackend>, path: &str, speed: f64) -> AudioResult<()> {
+    if !(speed > 0.0 && speed.is_finite()) {
+        return Err(AudioError::new(format!("The replay speed {} is invalid.", speed)));
+    }
+
+    let log = fs::read_to_string(path)?;
+    let entries = log.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(json::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut first_time = None;
+    let start = clock::now();
+    // ids in the log mapped to ids of the replayed audio instances
+    let mut ids = HashMap::new();
+
+    for entry in entries {
+        let time = match entry["Time"].as_str().map(DateTime::parse_from_rfc3339) {
+            Some(Ok(t)) => t,
+            _ => return Err(AudioError::new(format!("Missing or invalid time in command log entry {}.", entry)))
+        };
+
+        let offset = (time - *first_time.get_or_insert(time)).to_std().unwrap_or(Duration::ZERO);
+        let target = offset.div_f64(speed);
+        clock::sleep(target.saturating_sub(clock::elapsed(start)));
+
+        if entry.has_key("Command") {
+            let mut command = entry["Command"].clone();
+
+            if let Some(id) = command["ID"].as_u64() {
+                command["ID"] = ids.get(&id).copied().unwrap_or(id).into();
+            }
+
+            audio::write_command(backend, &command)?;
+        } else if let (Some(name), Some(id)) = (entry["Started"]["Name"].as_str(), entry["Started"]["ID"].as_u64()) {
+            ids.insert(id, audio::wait_for_start(backend, name, None, REPLAY_START_TIMEOUT)?);
+        }
+    }
+
+    Ok(())
+}
+
+pub(crate) fn log_command(command: &json::JsonValue) {
+    log_entry(object! {
+        Time: now_str(),
+        Command: command.clone()
+    });
+}
+
+pub(crate) fn log_started(name: &str, id: u64) {
+    log_entry(object! {
+        Time: now_str(),
+        Started: object! { Name: name, ID: id }
+    });
+}
+
+fn log_entry(entry: json::JsonValue) {
+    // clone the path so writing does not hold the lock
+    let path = match COMMAND_LOG.lock().unwrap().clone() {
+        Some(p) => p,
+        None => return
+    };
+
+    if let Err(e) = writer::write(&path, format!("{}\n", entry.dump())) {
+        log::warn!("Error in writing to command log {}. ({})", path, e);
+    }
+}
+
+fn now_str() -> String {
+    clock::utc_now().to_rfc3339()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::process;
+
+    use chrono::Utc;
+
+    use crate::backend::{source_status, status_with, MockBackend};
+
+    fn write_log(name: &str, lines: &[json::JsonValue]) -> String {
+        let path = env::temp_dir().join(format!("replit_audio_replay_test_{}_{}", name, process::id()));
+        let log = lines.iter().map(|l| format!("{}\n", l.dump())).collect::<String>();
+        fs::write(&path, log).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn test_replay_remaps_ids() {
+        let path = write_log("remap", &[
+            object! { Time: "2020-08-01T12:00:00Z", Command: object! { Type: "Tone", Name: "rust_audio_test_replay" } },
+            object! { Time: "2020-08-01T12:00:00.100Z", Started: object! { Name: "rust_audio_test_replay", ID: 3 } },
+            object! { Time: "2020-08-01T12:00:00.400Z", Command: object! { ID: 3, Volume: 0.5 } }
+        ]);
+        let backend = Arc::new(MockBackend::with_status(&status_with(vec![source_status("rust_audio_test_replay", 9, 1.0)])));
+
+        let _lock = clock::TEST_LOCK.lock().unwrap();
+        let clock = clock::MockClock::install();
+        let result = replay_on(&(Arc::clone(&backend) as Arc<dyn AudioBackend>), &path, 2.0);
+        clock::MockClock::uninstall();
+        fs::remove_file(&path).unwrap();
+
+        result.unwrap();
+        let commands = backend.commands();
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0]["Name"], "rust_audio_test_replay");
+        assert_eq!(commands[1]["ID"], 9);
+        assert_eq!(commands[1]["Volume"], 0.5);
+        // the time between commands is divided by the speed
+        assert!(clock.elapsed() >= Duration::from_millis(200));
+        assert!(clock.elapsed() < Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_replay_invalid_speed() {
+        let backend: Arc<dyn AudioBackend> = Arc::new(MockBackend::new());
+
+        for &speed in &[0.0, -1.0, f64::NAN, f64::INFINITY] {
+            // the speed is checked before the log is read
+            assert!(replay_on(&backend, "/nonexistent/replit_audio_replay_test", speed).unwrap_err().to_string().contains("speed"));
+        }
+    }
+
+    #[test]
+    fn test_replay_missing_time() {
+        let path = write_log("missing_time", &[
+            object! { Time: "2020-08-01T12:00:00Z", Command: object! { ID: 1, Volume: 0.5 } },
+            object! { Command: object! { ID: 1, Volume: 0.0 } }
+        ]);
+        let backend = Arc::new(MockBackend::new());
+        let result = replay_on(&(Arc::clone(&backend) as Arc<dyn AudioBackend>), &path, 1.0);
+        fs::remove_file(&path).unwrap();
+
+        assert!(result.unwrap_err().to_string().contains("Missing or invalid time"));
+        // commands before the invalid entry are still written
+        assert_eq!(backend.commands().len(), 1);
+    }
+
+    #[test]
+    fn test_now_str_mock_clock() {
+        let _lock = clock::TEST_LOCK.lock().unwrap();
+        let clock = clock::MockClock::install();
+        clock.advance(Duration::from_secs(3600));
+        let time = DateTime::parse_from_rfc3339(&now_str()).unwrap();
+        clock::MockClock::uninstall();
+
+        let expected = Utc::now() + chrono::Duration::seconds(3600);
+        assert!((expected - time.with_timezone(&Utc)).num_seconds().abs() < 5);
+    }
+}
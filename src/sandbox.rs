@@ -0,0 +1,297 @@
+//! A sandbox mode that simulates the repl.it audio playing program in memory, for testing
+//! audio logic without it.
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use json::{self, object};
+
+use crate::audio::{AudioError, AudioResult, AudioType};
+use crate::{clock, wav};
+
+// same format as the status file
+const SANDBOX_TIME_FORMAT: &str = "%FT%T%.9fZ";
+
+lazy_static! {
+    static ref SANDBOX: AtomicBool = AtomicBool::new(false);
+    static ref SANDBOX_STATE: Mutex<Sandbox> = Mutex::new(Sandbox { next_id: 1, sources: HashMap::new() });
+}
+
+struct Sandbox {
+    next_id: u64,
+    sources: HashMap<u64, Source>
+}
+
+struct Source {
+    name: String,
+    type_str: json::JsonValue,
+    args: json::JsonValue,
+    volume: f64,
+    pan: f64,
+    // zero if unknown, like for audio files
+    duration: Duration,
+    paused: bool,
+    does_loop: bool,
+    // number of loops left after the loop that is playing at the anchor
+    loop_count: i64,
+    // position in the loop that is playing at the anchor
+    anchor_position: Duration,
+    anchor: Instant
+}
+
+// where a source is at some point in time
+struct Progress {
+    position: Duration,
+    loop_count: i64,
+    finished: bool
+}
+
+/// Set whether audio is simulated in memory instead of played by the repl.it audio playing
+/// program.
+///
+/// In sandbox mode, `AudioBuilder::build` also checks that audio files exist and that the
+/// pitch and duration of tones are positive, and returns an error if that is not the
+/// case. Nothing is written to the backend (see `AudioBackend`) and its status is never
+/// read. Instead, the returned `Audio` is backed by a simulated audio source, whose
+/// status is computed from the crate's clock: the remaining time of a tone counts down
+/// from its duration, loops are counted down, and the audio source is removed once it
+/// finishes. Updates and stops are applied to the simulated audio source. The duration of
+/// WAV files is read from their headers (see `wav_duration`). Other audio files, and WAV
+/// files with malformed headers, have a duration of `0` and play until they are stopped.
+///
+/// Sandbox mode takes precedence over soft mode (see `set_soft_mode`). Simulated audio
+/// sources are kept when sandbox mode is disabled, so they are back if it is enabled again.
+///
+/// By default, sandbox mode is disabled.
+pub fn set_sandbox_mode(enabled: bool) {
+    SANDBOX.store(enabled, Ordering::SeqCst);
+}
+
+pub(crate) fn active() -> bool {
+    SANDBOX.load(Ordering::SeqCst)
+}
+
+pub(crate) fn validate(audio_type: &AudioType) -> AudioResult<()> {
+    match audio_type {
+        AudioType::File { path, .. } => {
+            if fs::metadata(path).is_err() {
+                return Err(AudioError::new(format!("The audio file {} does not exist.", path)));
+            }
+        },
+        AudioType::Tone { pitch, duration, .. } => {
+            if !(*pitch > 0.0 && pitch.is_finite()) {
+                return Err(AudioError::new(format!("The pitch {} is invalid.", pitch)));
+            }
+
+            if !(*duration > 0.0 && duration.is_finite()) {
+                return Err(AudioError::new(format!("The duration {} is invalid.", duration)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// apply a command that would have been written to the repl.it audio playing program
+pub(crate) fn apply(command: &json::JsonValue) {
+    SANDBOX_STATE.lock().unwrap().apply(command, clock::now());
+}
+
+// the simulated contents of the status file
+pub(crate) fn status() -> json::JsonValue {
+    SANDBOX_STATE.lock().unwrap().status(clock::now())
+}
+
+impl Sandbox {
+    fn apply(&mut self, command: &json::JsonValue, now: Instant) {
+        let id = match command["ID"].as_u64() {
+            Some(id) => id,
+            None => {
+                self.play(command, now);
+                return;
+            }
+        };
+
+        if command["Stop"].as_bool().unwrap_or(false) {
+            self.sources.remove(&id);
+            return;
+        }
+
+        // like the repl.it audio playing program, updates to missing audio sources are ignored
+        if let Some(source) = self.sources.get_mut(&id) {
+            source.reanchor(now);
+            source.volume = command["Volume"].as_f64().unwrap_or(source.volume);
+            source.pan = command["Pan"].as_f64().unwrap_or(source.pan);
+            source.paused = command["Paused"].as_bool().unwrap_or(source.paused);
+            source.does_loop = command["DoesLoop"].as_bool().unwrap_or(source.does_loop);
+            source.loop_count = command["LoopCount"].as_i64().unwrap_or(source.loop_count);
+        }
+    }
+
+    fn play(&mut self, command: &json::JsonValue, now: Instant) {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let duration = match (command["Type"].as_str(), command["Args"]["Path"].as_str()) {
+            (Some("wav"), Some(path)) => wav::wav_duration(path).unwrap_or(Duration::ZERO),
+            _ => Duration::from_secs_f64(command["Args"]["Seconds"].as_f64().unwrap_or(0.0).max(0.0))
+        };
+
+        self.sources.insert(id, Source {
+            name: command["Name"].as_str().unwrap_or("").to_owned(),
+            type_str: command["Type"].clone(),
+            args: command["Args"].clone(),
+            volume: command["Volume"].as_f64().unwrap_or(1.0),
+            pan: command["Pan"].as_f64().unwrap_or(0.0),
+            duration,
+            paused: false,
+            does_loop: command["DoesLoop"].as_bool().unwrap_or(false),
+            loop_count: command["LoopCount"].as_i64().unwrap_or(0),
+            anchor_position: Duration::ZERO,
+            anchor: now
+        });
+    }
+
+    fn status(&mut self, now: Instant) -> json::JsonValue {
+        self.sources.retain(|_, s| !s.progress(now).finished);
+
+        let mut ids = self.sources.keys().copied().collect::<Vec<_>>();
+        ids.sort_unstable();
+
+        let sources = ids.iter().map(|id| self.sources[id].to_json(*id, now)).collect::<Vec<_>>();
+        let running = self.sources.values().any(|s| !s.paused);
+
+        object! {
+            Sources: sources,
+            Running: running,
+            Disabled: false
+        }
+    }
+}
+
+impl Source {
+    fn progress(&self, now: Instant) -> Progress {
+        let mut position = self.anchor_position;
+
+        if !self.paused {
+            position += now.saturating_duration_since(self.anchor);
+        }
+
+        let loop_count = if self.does_loop { self.loop_count } else { 0 };
+
+        if self.duration.is_zero() {
+            return Progress { position: Duration::ZERO, loop_count, finished: false };
+        }
+
+        let loops_done = (position.as_nanos() / self.duration.as_nanos()) as i64;
+        let position = Duration::from_nanos((position.as_nanos() % self.duration.as_nanos()) as u64);
+
+        if loop_count < 0 {
+            Progress { position, loop_count, finished: false }
+        } else {
+            Progress { position, loop_count: loop_count - loops_done, finished: loops_done > loop_count }
+        }
+    }
+
+    fn reanchor(&mut self, now: Instant) {
+        let progress = self.progress(now);
+        self.anchor_position = progress.position;
+        self.loop_count = progress.loop_count;
+        self.anchor = now;
+    }
+
+    fn to_json(&self, id: u64, now: Instant) -> json::JsonValue {
+        let progress = self.progress(now);
+        let start_time = clock::utc_now() - chrono::Duration::from_std(progress.position).unwrap_or_else(|_| chrono::Duration::zero());
+        let end_time = start_time + chrono::Duration::from_std(self.duration).unwrap_or_else(|_| chrono::Duration::zero());
+
+        object! {
+            ID: id,
+            Name: self.name.as_str(),
+            Type: self.type_str.clone(),
+            Args: self.args.clone(),
+            Volume: self.volume,
+            Pan: self.pan,
+            Duration: self.duration.as_millis() as u64,
+            Remaining: (self.duration - progress.position).as_millis() as u64,
+            Paused: self.paused,
+            Loop: progress.loop_count,
+            StartTime: start_time.format(SANDBOX_TIME_FORMAT).to_string(),
+            EndTime: end_time.format(SANDBOX_TIME_FORMAT).to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::audio::{FileType, ToneType};
+
+    fn tone_command(loop_count: i64) -> json::JsonValue {
+        object! {
+            Name: "rust_audio_0",
+            Type: "tone",
+            Volume: 0.5,
+            DoesLoop: loop_count != 0,
+            LoopCount: loop_count,
+            Args: object! { WaveType: 0, Pitch: 440.0, Seconds: 2.0 }
+        }
+    }
+
+    #[test]
+    fn test_sandbox_counts_down() {
+        let now = Instant::now();
+        let mut sandbox = Sandbox { next_id: 1, sources: HashMap::new() };
+        sandbox.apply(&tone_command(1), now);
+
+        let status = sandbox.status(now + Duration::from_millis(500));
+        assert_eq!(status["Sources"][0]["ID"], 1);
+        assert_eq!(status["Sources"][0]["Name"], "rust_audio_0");
+        assert_eq!(status["Sources"][0]["Remaining"], 1500);
+        assert_eq!(status["Sources"][0]["Loop"], 1);
+        assert_eq!(status["Running"], true);
+
+        // the second loop
+        let status = sandbox.status(now + Duration::from_millis(2500));
+        assert_eq!(status["Sources"][0]["Remaining"], 1500);
+        assert_eq!(status["Sources"][0]["Loop"], 0);
+
+        assert!(sandbox.status(now + Duration::from_millis(4000))["Sources"].is_empty());
+    }
+
+    #[test]
+    fn test_sandbox_update_and_stop() {
+        let now = Instant::now();
+        let mut sandbox = Sandbox { next_id: 1, sources: HashMap::new() };
+        sandbox.apply(&tone_command(-1), now);
+
+        let pause = object! { ID: 1, Volume: 0.25, Paused: true, DoesLoop: true, LoopCount: -1 };
+        sandbox.apply(&pause, now + Duration::from_millis(500));
+
+        // the remaining time does not change while paused
+        let status = sandbox.status(now + Duration::from_secs(60));
+        assert_eq!(status["Sources"][0]["Remaining"], 1500);
+        assert_eq!(status["Sources"][0]["Volume"], 0.25);
+        assert_eq!(status["Sources"][0]["Loop"], -1);
+        assert_eq!(status["Running"], false);
+
+        sandbox.apply(&object! { ID: 1, Stop: true }, now + Duration::from_secs(61));
+        assert!(sandbox.status(now + Duration::from_secs(61))["Sources"].is_empty());
+    }
+
+    #[test]
+    fn test_validate() {
+        let tone = |pitch, duration| AudioType::Tone { tone: ToneType::Sine, pitch, duration };
+
+        assert!(validate(&tone(440.0, 1.0)).is_ok());
+        assert!(validate(&tone(0.0, 1.0)).is_err());
+        assert!(validate(&tone(440.0, f64::NAN)).is_err());
+        assert!(validate(&AudioType::File { file: FileType::Wav, path: "missing.wav".to_owned() }).is_err());
+        assert!(validate(&AudioType::File { file: FileType::Wav, path: "Cargo.toml".to_owned() }).is_ok());
+    }
+}
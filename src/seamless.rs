@@ -0,0 +1,126 @@
+//! Looping with overlapping crossfades, for audio files that click when looped.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::audio::{self, Audio, AudioBuilder, AudioResult, AudioUpdate};
+use crate::clock;
+
+const CROSSFADE_STEP: Duration = Duration::from_millis(50);
+const REMAINING_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A handle to an audio instance that is looped seamlessly by `AudioBuilder::seamless_loop`.
+///
+/// Dropping the handle or calling `stop` stops all of the audio instances used for the loop
+/// and the background thread.
+pub struct SeamlessLoop {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>
+}
+
+impl AudioBuilder {
+    /// Play the audio instance forever, by starting a new audio instance `overlap` before
+    /// the current one ends and crossfading between them, instead of looping.
+    ///
+    /// This avoids clicks when the end of the audio does not line up with its start. A
+    /// background thread starts and crossfades the audio instances, so two audio instances
+    /// play at a time during each crossfade. The loop settings of the builder are ignored.
+    /// This blocks until the first audio instance begins playing.
+    pub fn seamless_loop(&self, overlap: Duration) -> AudioResult<SeamlessLoop> {
+        let builder = self.clone().does_loop(false).loop_count(0);
+        let volume = builder.volume;
+        let first = builder.build()?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+
+        // a dedicated thread, since the loop runs until it is stopped
+        let handle = thread::spawn(move || {
+            let mut current = first;
+
+            let result = run(&builder, volume, overlap, &thread_stop, &mut current);
+
+            if let Err(e) = result {
+                log::warn!("Stopped seamless loop. ({})", e);
+            }
+
+            let _ = audio::write_stop(&builder.backend, current.get_id());
+        });
+
+        Ok(SeamlessLoop { stop, handle: Some(handle) })
+    }
+}
+
+fn run(builder: &AudioBuilder, volume: f64, overlap: Duration, stop: &AtomicBool, current: &mut Audio) -> AudioResult<()> {
+    loop {
+        // wait until the current audio instance is about to end
+        loop {
+            if stop.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+
+            let remaining = match audio::find_status_by_id(&builder.backend, current.get_id())? {
+                Some(s) => audio::remaining_from_status(&s),
+                None => 0
+            };
+
+            if remaining as u128 <= overlap.as_millis() {
+                break;
+            }
+
+            clock::sleep(REMAINING_POLL_INTERVAL);
+        }
+
+        let next = builder.clone().volume(0.0).build()?;
+        let start = clock::now();
+
+        loop {
+            let t = if overlap.as_secs_f64() > 0.0 {
+                (clock::elapsed(start).as_secs_f64() / overlap.as_secs_f64()).min(1.0)
+            } else {
+                1.0
+            };
+
+            // the current audio instance may have ended on its own already
+            let _ = audio::update_source(&builder.backend, current.get_id(), &fade_update(volume * (1.0 - t), builder.pan));
+            let result = audio::update_source(&builder.backend, next.get_id(), &fade_update(volume * t, builder.pan));
+
+            if let Err(e) = result {
+                let _ = audio::write_stop(&builder.backend, next.get_id());
+                return Err(e);
+            }
+
+            if t >= 1.0 || stop.load(Ordering::SeqCst) {
+                break;
+            }
+
+            clock::sleep(CROSSFADE_STEP);
+        }
+
+        audio::write_stop(&builder.backend, current.get_id())?;
+        *current = next;
+    }
+}
+
+fn fade_update(volume: f64, pan: f64) -> AudioUpdate {
+    AudioUpdate { volume, pan, paused: false, does_loop: false, loop_count: 0 }
+}
+
+impl SeamlessLoop {
+    /// Stop the loop and all of its audio instances.
+    pub fn stop(self) {
+        // dropping does the work
+    }
+}
+
+impl Drop for SeamlessLoop {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,87 @@
+//! A registry of the background tasks for each audio source, so they can be cancelled
+//! without stopping the audio source.
+//!
+//! Tasks are registered by the backend and the id of their audio source, since audio
+//! sources of different backends can have the same id.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use lazy_static::lazy_static;
+
+use crate::backend::{self, AudioBackend, SourceKey};
+
+lazy_static! {
+    // cancellation flags of the running background tasks for each audio source, keyed by
+    // backend::source_key
+    static ref TASKS: Mutex<HashMap<SourceKey, Vec<Arc<AtomicBool>>>> = Mutex::new(HashMap::new());
+}
+
+// returns a flag that is set once the task should stop
+pub(crate) fn register(backend: &Arc<dyn AudioBackend>, id: u64) -> Arc<AtomicBool> {
+    let cancelled = Arc::new(AtomicBool::new(false));
+    TASKS.lock().unwrap().entry(backend::source_key(backend, id)).or_default().push(Arc::clone(&cancelled));
+    cancelled
+}
+
+// should be called once a task stops, whether or not it was cancelled
+pub(crate) fn unregister(backend: &Arc<dyn AudioBackend>, id: u64, cancelled: &Arc<AtomicBool>) {
+    let key = backend::source_key(backend, id);
+    let mut tasks = TASKS.lock().unwrap();
+
+    if let Some(flags) = tasks.get_mut(&key) {
+        flags.retain(|f| !Arc::ptr_eq(f, cancelled));
+
+        if flags.is_empty() {
+            tasks.remove(&key);
+        }
+    }
+}
+
+pub(crate) fn cancel(backend: &Arc<dyn AudioBackend>, id: u64) {
+    if let Some(flags) = TASKS.lock().unwrap().remove(&backend::source_key(backend, id)) {
+        for flag in flags {
+            flag.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::backend::MockBackend;
+
+    #[test]
+    fn test_cancel() {
+        let backend: Arc<dyn AudioBackend> = Arc::new(MockBackend::new());
+        let a = register(&backend, 1);
+        let b = register(&backend, 1);
+        let other = register(&backend, 2);
+
+        unregister(&backend, 1, &b);
+        cancel(&backend, 1);
+        assert!(a.load(Ordering::SeqCst));
+        assert!(!b.load(Ordering::SeqCst));
+        assert!(!other.load(Ordering::SeqCst));
+
+        unregister(&backend, 2, &other);
+        assert!(!TASKS.lock().unwrap().contains_key(&backend::source_key(&backend, 2)));
+    }
+
+    #[test]
+    fn test_cancel_other_backend() {
+        let backend: Arc<dyn AudioBackend> = Arc::new(MockBackend::new());
+        let other_backend: Arc<dyn AudioBackend> = Arc::new(MockBackend::new());
+        let task = register(&backend, 1);
+        let other = register(&other_backend, 1);
+
+        // the same id on another backend is a different audio source
+        cancel(&other_backend, 1);
+        assert!(!task.load(Ordering::SeqCst));
+        assert!(other.load(Ordering::SeqCst));
+
+        unregister(&backend, 1, &task);
+    }
+}
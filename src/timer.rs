@@ -0,0 +1,133 @@
+//! A single thread that runs short tasks once their deadline passes, like stopping an audio
+//! instance after `AudioBuilder::play_for` and writing merged updates.
+//!
+//! Waiting for a deadline does not take up a thread, so any number of tasks can be waiting
+//! at a time. The tasks run one after another on the timer thread, so they should only do
+//! a little work, like writing a command, and schedule themselves again to do more later.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+
+use crate::clock;
+
+// the deadlines are checked at least this often, so they also pass when a mock clock is
+// advanced without waking up the timer thread
+const MAX_WAIT: Duration = Duration::from_millis(10);
+
+type Task = Box<dyn FnOnce() + Send + 'static>;
+
+struct Entry {
+    deadline: Instant,
+    // tasks with the same deadline run in the order they were scheduled
+    seq: u64,
+    task: Task
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    // reversed, so the heap pops the earliest deadline first
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.deadline, other.seq).cmp(&(self.deadline, self.seq))
+    }
+}
+
+struct Timer {
+    entries: BinaryHeap<Entry>,
+    next_seq: u64,
+    running: bool
+}
+
+lazy_static! {
+    static ref TIMER: (Mutex<Timer>, Condvar) = (
+        Mutex::new(Timer { entries: BinaryHeap::new(), next_seq: 0, running: false }),
+        Condvar::new()
+    );
+}
+
+// the thread is only spawned once the first task is scheduled
+pub(crate) fn schedule<F: FnOnce() + Send + 'static>(delay: Duration, task: F) {
+    let (lock, cvar) = &*TIMER;
+    let mut timer = lock.lock().unwrap();
+    let seq = timer.next_seq;
+    timer.next_seq += 1;
+    timer.entries.push(Entry { deadline: clock::now() + delay, seq, task: Box::new(task) });
+
+    if !timer.running {
+        timer.running = true;
+        thread::spawn(run);
+    }
+
+    cvar.notify_one();
+}
+
+fn run() {
+    let (lock, cvar) = &*TIMER;
+    let mut timer = lock.lock().unwrap();
+
+    loop {
+        let now = clock::now();
+
+        timer = match timer.entries.peek().map(|e| e.deadline) {
+            Some(deadline) if deadline <= now => {
+                let entry = timer.entries.pop().unwrap();
+                drop(timer);
+                // a panicking task should not take down the thread
+                let _ = panic::catch_unwind(AssertUnwindSafe(entry.task));
+                lock.lock().unwrap()
+            },
+            Some(deadline) => cvar.wait_timeout(timer, (deadline - now).min(MAX_WAIT)).unwrap().0,
+            None => cvar.wait(timer).unwrap()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_schedule() {
+        let (tx, rx) = mpsc::channel();
+        let _lock = clock::TEST_LOCK.lock().unwrap();
+        let start = Instant::now();
+
+        for &(delay, i) in &[(60, 2), (20, 0), (40, 1), (40, 3)] {
+            let tx = tx.clone();
+            schedule(Duration::from_millis(delay), move || tx.send(i).unwrap());
+        }
+
+        let order = (0..4).map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap()).collect::<Vec<_>>();
+        assert_eq!(order, vec![0, 1, 3, 2]);
+        assert!(start.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[test]
+    fn test_schedule_panic() {
+        let (tx, rx) = mpsc::channel();
+        let _lock = clock::TEST_LOCK.lock().unwrap();
+        schedule(Duration::ZERO, || panic!("timer task panicked"));
+        schedule(Duration::ZERO, move || tx.send(()).unwrap());
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+}
@@ -0,0 +1,114 @@
+//! Reading the duration of WAV files from their headers, without playing them.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::time::Duration;
+
+use crate::audio::{AudioError, AudioResult};
+
+/// Get the duration of a WAV file by reading its header, without playing it.
+///
+/// The duration is the size of the `data` chunk divided by the byte rate in the `fmt `
+/// chunk. An error is returned if the file cannot be read, is not a WAV file, or has a
+/// malformed header.
+pub fn wav_duration(path: &str) -> AudioResult<Duration> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    match duration_from_reader(&mut reader) {
+        Ok(Some(d)) => Ok(d),
+        Ok(None) => Err(AudioError::new(format!("{} is not a valid WAV file.", path))),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            Err(AudioError::new(format!("{} is not a valid WAV file.", path)))
+        },
+        Err(e) => Err(e.into())
+    }
+}
+
+// returns None if the header is malformed
+fn duration_from_reader<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Duration>> {
+    let mut riff = [0u8; 12];
+    reader.read_exact(&mut riff)?;
+
+    if &riff[0..4] != b"RIFF" || &riff[8..12] != b"WAVE" {
+        return Ok(None);
+    }
+
+    let mut byte_rate = None;
+
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk)?;
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+
+        match &chunk[0..4] {
+            b"fmt " => {
+                if size < 16 {
+                    return Ok(None);
+                }
+
+                let mut fmt = [0u8; 16];
+                reader.read_exact(&mut fmt)?;
+                byte_rate = Some(u32::from_le_bytes([fmt[8], fmt[9], fmt[10], fmt[11]]));
+                // skip the rest of the chunk, including the padding byte for odd sizes
+                reader.seek(SeekFrom::Current(size as i64 - 16 + (size % 2) as i64))?;
+            },
+            b"data" => {
+                return match byte_rate {
+                    Some(r) if r > 0 => Ok(Some(Duration::from_secs_f64(size as f64 / r as f64))),
+                    _ => Ok(None)
+                };
+            },
+            _ => {
+                reader.seek(SeekFrom::Current(size as i64 + (size % 2) as i64))?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    // a header for 16-bit mono audio at 8000 Hz, with an extra chunk before the data
+    fn header(data_size: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + 10 + data_size).to_le_bytes());
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(b"fmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&8000u32.to_le_bytes());
+        bytes.extend_from_slice(&16000u32.to_le_bytes());
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&16u16.to_le_bytes());
+        bytes.extend_from_slice(b"LIST");
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(&[0, 0]);
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_size.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_duration_from_reader() {
+        let duration = duration_from_reader(&mut Cursor::new(header(24000))).unwrap();
+        assert_eq!(duration, Some(Duration::from_millis(1500)));
+
+        let mut not_wav = header(24000);
+        not_wav[8..12].copy_from_slice(b"AVI ");
+        assert_eq!(duration_from_reader(&mut Cursor::new(not_wav)).unwrap(), None);
+
+        let truncated = header(24000)[..30].to_vec();
+        assert!(duration_from_reader(&mut Cursor::new(truncated)).is_err());
+    }
+
+    #[test]
+    fn test_wav_duration_missing_file() {
+        assert!(wav_duration("missing.wav").is_err());
+        assert!(wav_duration("Cargo.toml").is_err());
+    }
+}
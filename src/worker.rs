@@ -0,0 +1,124 @@
+//! A small pool of worker threads for running background tasks.
+//!
+//! Only `AudioBuilder::build_async` runs on the pool, so the number of its threads is
+//! bounded. Tasks that wait for a deadline, like stopping an audio instance after
+//! `AudioBuilder::play_for` and writing merged updates, run on the single timer thread
+//! instead (see `timer`), so they never wait for a free worker thread. Tasks that run until
+//! they are stopped or until an audio instance finishes are not bounded, and each get their
+//! own thread, since they could tie up every worker thread and leave other tasks waiting
+//! forever. These are waiting for `AudioBuilder::completion_sender`, `Audio::follow`,
+//! `AudioBuilder::seamless_loop`, `AudioBuilder::loop_with_gap`, `Metronome`, and the
+//! ducking watcher.
+
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+
+use lazy_static::lazy_static;
+
+const DEFAULT_WORKER_THREADS: usize = 4;
+
+type Task = Box<dyn FnOnce() + Send + 'static>;
+
+struct Pool {
+    tasks: VecDeque<Task>,
+    max_threads: usize,
+    threads: usize,
+    idle: usize
+}
+
+lazy_static! {
+    static ref POOL: (Mutex<Pool>, Condvar) = (
+        Mutex::new(Pool { tasks: VecDeque::new(), max_threads: DEFAULT_WORKER_THREADS, threads: 0, idle: 0 }),
+        Condvar::new()
+    );
+}
+
+/// Set the maximum number of threads used to play audio instances in the background with
+/// `AudioBuilder::build_async`.
+///
+/// Audio instances that are played while all threads are busy wait until a thread is
+/// free. Other background tasks, like `AudioBuilder::play_for` and `Audio::follow`, do not
+/// use these threads. Lowering the number of threads does not interrupt running tasks;
+/// extra threads exit once they finish their current task. The number of threads is at
+/// least `1`.
+///
+/// By default, at most `4` threads are used.
+pub fn set_worker_threads(n: usize) {
+    let (lock, cvar) = &*POOL;
+    lock.lock().unwrap().max_threads = n.max(1);
+    // wake up idle threads so extra ones can exit
+    cvar.notify_all();
+}
+
+// threads are only spawned when a task is queued and no thread is idle
+pub(crate) fn execute<F: FnOnce() + Send + 'static>(task: F) {
+    let (lock, cvar) = &*POOL;
+    let mut pool = lock.lock().unwrap();
+    pool.tasks.push_back(Box::new(task));
+
+    if pool.idle == 0 && pool.threads < pool.max_threads {
+        pool.threads += 1;
+        thread::spawn(work);
+    } else {
+        cvar.notify_one();
+    }
+}
+
+fn work() {
+    let (lock, cvar) = &*POOL;
+    let mut pool = lock.lock().unwrap();
+
+    loop {
+        if pool.threads > pool.max_threads {
+            pool.threads -= 1;
+            return;
+        }
+
+        match pool.tasks.pop_front() {
+            Some(task) => {
+                drop(pool);
+                // a panicking task should not take down the thread
+                let _ = panic::catch_unwind(AssertUnwindSafe(task));
+                pool = lock.lock().unwrap();
+            },
+            None => {
+                pool.idle += 1;
+                pool = cvar.wait(pool).unwrap();
+                pool.idle -= 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_execute() {
+        let (tx, rx) = mpsc::channel();
+
+        for i in 0..16 {
+            let tx = tx.clone();
+            execute(move || tx.send(i).unwrap());
+        }
+
+        let mut received = (0..16).map(|_| rx.recv_timeout(Duration::from_secs(5)).unwrap()).collect::<Vec<_>>();
+        received.sort();
+        assert_eq!(received, (0..16).collect::<Vec<_>>());
+        assert!(POOL.0.lock().unwrap().threads <= DEFAULT_WORKER_THREADS);
+    }
+
+    #[test]
+    fn test_execute_panic() {
+        let (tx, rx) = mpsc::channel();
+        execute(|| panic!("background task panicked"));
+        execute(move || tx.send(()).unwrap());
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+//! A single thread that writes all commands to the repl.it audio playing program, so
+//! commands from different threads are never interleaved.
+//!
+//! Each command is also written while holding an exclusive advisory lock on the file, so
+//! commands from other programs that lock the file are not interleaved either.
+
+use std::fs;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Mutex;
+use std::thread;
+
+use lazy_static::lazy_static;
+
+use crate::audio::{AudioError, AudioResult};
+
+struct WriteRequest {
+    path: String,
+    command: String,
+    ack: Sender<io::Result<()>>
+}
+
+lazy_static! {
+    static ref WRITER: Mutex<Sender<WriteRequest>> = {
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || run(rx));
+        Mutex::new(tx)
+    };
+}
+
+// block until the writer thread has appended the command to the file at the path
+pub(crate) fn write(path: &str, command: String) -> AudioResult<()> {
+    let (ack, result) = mpsc::channel();
+    let request = WriteRequest { path: path.to_owned(), command, ack };
+
+    if WRITER.lock().unwrap().send(request).is_err() {
+        return Err(AudioError::new("The command writer thread stopped.".to_owned()));
+    }
+
+    match result.recv() {
+        Ok(r) => Ok(r?),
+        Err(_) => Err(AudioError::new("The command writer thread stopped.".to_owned()))
+    }
+}
+
+fn run(rx: Receiver<WriteRequest>) {
+    for request in rx {
+        let result = fs::OpenOptions::new()
+            .append(true)
+            .open(&request.path)
+            .and_then(|file| write_locked(file, &request.command));
+
+        // the requesting thread may have stopped waiting
+        let _ = request.ack.send(result);
+    }
+}
+
+fn write_locked(mut file: fs::File, command: &str) -> io::Result<()> {
+    match file.lock() {
+        Ok(()) => (),
+        // some file systems do not support locking, and a single append is usually not
+        // interleaved anyways
+        Err(e) if e.kind() == io::ErrorKind::Unsupported => (),
+        Err(e) => return Err(e)
+    }
+
+    // the lock is released when the file is closed
+    file.write_all(command.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::env;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_concurrent_writes() {
+        let path = env::temp_dir().join(format!("replit_audio_writer_test_{}", std::process::id()));
+        fs::write(&path, "").unwrap();
+        let path = Arc::new(path.to_str().unwrap().to_owned());
+
+        let handles = (0..8).map(|i| {
+            let path = Arc::clone(&path);
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    write(&path, format!("{{\"Thread\":{}}}\n", i)).unwrap();
+                }
+            })
+        }).collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let contents = fs::read_to_string(&*path).unwrap();
+        fs::remove_file(&*path).unwrap();
+
+        let lines = contents.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 400);
+        assert!(lines.iter().all(|l| json::parse(l).is_ok()));
+    }
+
+    #[test]
+    fn test_write_waits_for_lock() {
+        let path = env::temp_dir().join(format!("replit_audio_writer_lock_test_{}", std::process::id()));
+        fs::write(&path, "").unwrap();
+
+        // another program holds the lock
+        let other = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        other.lock().unwrap();
+
+        let writer = {
+            let path = path.to_str().unwrap().to_owned();
+            thread::spawn(move || write(&path, "{}\n".to_owned()).unwrap())
+        };
+
+        thread::sleep(std::time::Duration::from_millis(50));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        other.unlock().unwrap();
+        writer.join().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{}\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_missing_file() {
+        assert!(write("/nonexistent_replit_audio_dir/audio", "{}".to_owned()).is_err());
+    }
+}
@@ -0,0 +1,52 @@
+use replit_audio::*;
+
+use std::env;
+use std::fs;
+
+// this runs in its own process, so changing the paths does not affect the other tests
+#[test]
+fn test_custom_paths() {
+    let dir = env::temp_dir();
+    let update_path = dir.join(format!("replit_audio_update_{}", std::process::id()));
+    let status_path = dir.join(format!("replit_audio_status_{}.json", std::process::id()));
+
+    fs::write(&update_path, "").unwrap();
+    fs::write(&status_path, r#"{
+        "Sources": [{
+            "Name": "rust_audio_test_paths", "Type": "tone", "ID": 7,
+            "Volume": 0.5, "Duration": 1000, "Remaining": 500, "Paused": false, "Loop": 0,
+            "StartTime": "2020-08-01T12:00:00Z", "EndTime": "2020-08-01T12:00:01Z"
+        }],
+        "Running": true,
+        "Disabled": false
+    }"#).unwrap();
+
+    env::set_var(UPDATE_PATH_ENV_VAR, &update_path);
+    env::set_var(STATUS_PATH_ENV_VAR, &status_path);
+
+    let sources = replit_audio::list_sources().unwrap();
+    assert_eq!(sources.len(), 1);
+    assert_eq!(sources[0].name, "rust_audio_test_paths");
+    assert_eq!(replit_audio::detect_environment(), Environment::Replit);
+
+    AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+        .volume(0.25)
+        .name("rust_audio_test_fire")
+        .fire()
+        .unwrap();
+    replit_audio::stop_all().unwrap();
+
+    let written = fs::read_to_string(&update_path).unwrap();
+    assert!(written.contains(r#""Name":"rust_audio_test_fire""#));
+    assert!(written.contains(r#""ID":7"#));
+    assert!(written.contains(r#""Stop":true"#));
+
+    // the paths are looked up again on every call
+    env::set_var(STATUS_PATH_ENV_VAR, dir.join("replit_audio_missing_status.json"));
+    assert!(matches!(replit_audio::list_sources(), Err(AudioError::NotOnReplit { .. })));
+
+    env::remove_var(UPDATE_PATH_ENV_VAR);
+    env::remove_var(STATUS_PATH_ENV_VAR);
+    fs::remove_file(&update_path).unwrap();
+    fs::remove_file(&status_path).unwrap();
+}
@@ -1,21 +1,64 @@
+// the original asserts compare against bool literals
+#![allow(clippy::bool_assert_comparison)]
+
 use replit_audio::*;
 
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
 use std::thread;
 use std::time::Duration;
 
 #[test]
 fn test() {
     // this test should be conducted on repl.it
+    replit_audio::wait_until_ready(Duration::from_secs(5)).unwrap();
     // first, play a tone, then turn down the volume halfway through
     test_play_tone();
     thread::sleep(Duration::from_secs(2));
+    // play a couple of quiet tones with known names
+    test_build_all_named();
+    thread::sleep(Duration::from_secs(1));
+    // play a tone without waiting, then make sure it began playing
+    test_fire();
+    thread::sleep(Duration::from_secs(1));
+    // check the status of a tone before and after it ends
+    test_try_status();
+    // stop a tone early
+    test_stop();
+    // fade a tone out
+    test_fade();
+    // get notified when a tone ends
+    test_completion_sender();
+    // record a tone and replay it
+    test_command_log();
+    // loop a tone with crossfades for a few iterations
+    test_seamless_loop();
+    // play only the first second of the mysterious audio file
+    test_play_for();
     // finally, play 30 seconds of the mysterious audio file
     test_play_audio_file();
     thread::sleep(Duration::from_secs(30));
+    // then silence everything
+    replit_audio::stop_all().unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert!(replit_audio::list_sources().unwrap().is_empty());
+    // stopping with nothing playing is fine
+    replit_audio::stop_all().unwrap();
+}
+
+fn test_play_for() {
+    let start = std::time::Instant::now();
+    let audio = AudioBuilder::file(FileType::Wav, "audio.wav")
+        .play_for(Duration::from_secs(1))
+        .build()
+        .unwrap();
+
+    audio.wait().unwrap();
+    assert!(start.elapsed() >= Duration::from_secs(1));
 }
 
 fn test_play_audio_file() {
-    let audio = AudioBuilder::new(&AudioType::File { file: FileType::Wav, path: "audio.wav".to_string() })
+    let mut audio = AudioBuilder::file(FileType::Wav, "audio.wav")
         .volume(1.0)
         .does_loop(true)
         .loop_count(-1)
@@ -24,24 +67,76 @@ fn test_play_audio_file() {
 
     assert_eq!(audio.get_volume().unwrap(), 1.0);
     assert_eq!(audio.get_loop().unwrap(), -1);
+    assert_eq!(audio.configured_loop_count(), -1);
+    assert_eq!(audio.true_end_time().unwrap(), None);
     audio.get_duration().unwrap();
     audio.get_remaining().unwrap();
     audio.get_start_time().unwrap();
     audio.get_end_time().unwrap();
+    assert_eq!(audio.get_start_time_utc().unwrap().naive_utc(), audio.get_start_time().unwrap());
     audio.is_paused().unwrap();
+    audio.is_mono().unwrap();
+    audio.get_peak().unwrap();
+    audio.get_rms().unwrap();
 
     assert_eq!(replit_audio::is_disabled().unwrap(), false);
     assert_eq!(replit_audio::is_running().unwrap(), true);
+
+    let (loop_count, progress) = audio.loop_progress().unwrap();
+    assert_eq!(loop_count, -1);
+    assert!((0.0..=1.0).contains(&progress));
+    assert!((0.0..=1.0).contains(&audio.get_progress().unwrap()));
+
+    audio.set_loop_count_preserving_position(-1).unwrap();
+    assert_eq!(audio.get_volume().unwrap(), 1.0);
+
+    audio.pause().unwrap();
+    // pausing again does nothing
+    audio.pause().unwrap();
+    assert!(audio.wait_for(|s| s.paused, Duration::from_millis(500)).unwrap());
+    assert_eq!(audio.get_loop().unwrap(), -1);
+    audio.resume().unwrap();
+    assert!(audio.wait_for(|s| !s.paused, Duration::from_millis(500)).unwrap());
+    assert_eq!(audio.get_volume().unwrap(), 1.0);
+
+    assert!(audio.set_volume(1.5).is_err());
+    audio.set_volume(0.75).unwrap();
+    assert!(audio.wait_for(|s| s.volume == 0.75, Duration::from_millis(500)).unwrap());
+    assert_eq!(audio.get_loop().unwrap(), -1);
+    audio.set_volume(1.0).unwrap();
+
+    let follower = audio.follow(Duration::from_millis(50), || 0.5);
+    thread::sleep(Duration::from_millis(200));
+    drop(follower);
+    assert_eq!(audio.get_volume().unwrap(), 0.5);
+
+    // the volume stops following once the background tasks are cancelled
+    let _follower = audio.follow(Duration::from_millis(50), || 0.25);
+    thread::sleep(Duration::from_millis(200));
+    audio.cancel_background_tasks();
+    thread::sleep(Duration::from_millis(100));
+    audio.update(&AudioUpdate { volume: 1.0, pan: 0.0, paused: false, does_loop: true, loop_count: -1 }).unwrap();
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(audio.get_volume().unwrap(), 1.0);
 }
 
 fn test_play_tone() {
-    let mut audio = AudioBuilder::new(&AudioType::Tone { tone: ToneType::Square, pitch: 440.0, duration: 2.0 })
+    let next_name = replit_audio::peek_next_name();
+    let mut audio = AudioBuilder::tone(ToneType::Square, 440.0, 2.0)
         .build()
         .unwrap();
 
     assert_eq!(audio.get_volume().unwrap(), 1.0);
+    assert_eq!(audio.get_volume_linear_and_db().unwrap(), (1.0, 0.0));
+    assert_eq!(audio.get_name().unwrap(), next_name);
+    assert_eq!(audio.name(), next_name);
     assert_eq!(audio.get_loop().unwrap(), 0);
+    assert_eq!(audio.configured_loop_count(), 0);
+    assert!(audio.true_end_time().unwrap().is_some());
     assert_eq!(audio.get_duration().unwrap(), 2000);
+    assert_eq!(audio.get_duration_as().unwrap(), Duration::from_secs(2));
+    assert!(audio.get_remaining_as().unwrap() <= Duration::from_secs(2));
+    assert_eq!(audio.status().unwrap().duration, 2000);
     audio.get_remaining().unwrap();
     audio.get_start_time().unwrap();
     audio.get_end_time().unwrap();
@@ -52,5 +147,254 @@ fn test_play_tone() {
 
     thread::sleep(Duration::from_secs(1));
 
-    audio.update(&AudioUpdate { volume: 0.1, paused: false, does_loop: false, loop_count: -1 }).unwrap();
+    audio.update(&AudioUpdate { volume: 0.1, pan: 0.0, paused: false, does_loop: false, loop_count: -1 }).unwrap();
+    assert!(audio.wait_for(|s| s.volume < 0.5, Duration::from_millis(500)).unwrap());
+}
+
+fn test_build_all_named() {
+    let audios = AudioBuilder::tone(ToneType::Sine, 660.0, 1.0)
+        .volume(0.1)
+        .build_all_named(&["rust_audio_test_a", "rust_audio_test_b"])
+        .unwrap();
+
+    assert_eq!(audios.len(), 2);
+    assert_eq!(audios[0].get_name().unwrap(), "rust_audio_test_a");
+    assert_eq!(audios[1].get_name().unwrap(), "rust_audio_test_b");
+
+    // the names are already taken by the tones that are still playing
+    assert!(matches!(AudioBuilder::tone(ToneType::Sine, 660.0, 1.0).build_all_named(&["rust_audio_test_a"]),
+        Err(AudioError::DuplicateName { name }) if name == "rust_audio_test_a"));
+
+    let sources = replit_audio::list_sources().unwrap();
+    assert!(sources.iter().any(|s| s.name == "rust_audio_test_a"));
+    assert!(sources.iter().any(|s| s.id == audios[1].get_id()));
+
+    assert_eq!(replit_audio::stop_where(|s| s.name == "rust_audio_test_b").unwrap(), 1);
+}
+
+fn test_fire() {
+    let pending = AudioBuilder::tone(ToneType::Triangle, 330.0, 1.0)
+        .volume(0.1)
+        .fire()
+        .unwrap();
+
+    let audio = pending.await_start(Duration::from_secs(2)).unwrap();
+    assert_eq!(audio.get_duration().unwrap(), 1000);
+
+    // block until the tone ends, instead of guessing with a sleep
+    assert!(!audio.is_finished().unwrap());
+    audio.wait().unwrap();
+    thread::sleep(Duration::from_millis(100));
+    assert!(audio.try_status().unwrap().is_none());
+    assert!(audio.is_finished().unwrap());
+}
+
+fn test_try_status() {
+    let audio = AudioBuilder::tone(ToneType::Sine, 440.0, 0.25)
+        .volume(0.1)
+        .build()
+        .unwrap();
+
+    assert!(audio.try_status().unwrap().is_some());
+    assert!(audio.dump_handle_json().unwrap().contains(&audio.get_name().unwrap()));
+    assert!(replit_audio::dump_status_json().unwrap().contains("Sources"));
+    thread::sleep(Duration::from_secs(1));
+    assert!(audio.try_status().unwrap().is_none());
+
+    let mut audio = AudioBuilder::tone(ToneType::Sine, 440.0, 5.0)
+        .volume(0.1)
+        .build()
+        .unwrap();
+
+    audio.stop_and_wait(Duration::from_secs(2)).unwrap();
+    assert!(audio.try_status().unwrap().is_none());
+}
+
+fn test_stop() {
+    let mut audio = AudioBuilder::tone(ToneType::Sine, 440.0, 5.0)
+        .volume(0.1)
+        .build()
+        .unwrap();
+
+    audio.stop().unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert!(audio.is_paused().is_err());
+    assert!(audio.get_remaining().is_err());
+    // the audio source is already gone
+    assert!(audio.stop().is_err());
+}
+
+fn test_fade() {
+    let mut audio = AudioBuilder::tone(ToneType::Sine, 440.0, 2.0)
+        .volume(0.2)
+        .build()
+        .unwrap();
+
+    audio.fade_to(0.0, Duration::from_millis(500)).unwrap();
+    assert!(audio.wait_for(|s| s.volume == 0.0, Duration::from_millis(500)).unwrap());
+    audio.fade_to(0.2, Duration::from_millis(250)).unwrap();
+    audio.fade_out_and_stop(Duration::from_millis(250)).unwrap();
+    thread::sleep(Duration::from_millis(500));
+    assert!(audio.try_status().unwrap().is_none());
+
+    // a tone that ends during the fade is fine
+    let mut audio = AudioBuilder::tone(ToneType::Sine, 440.0, 0.25)
+        .volume(0.2)
+        .build()
+        .unwrap();
+    audio.fade_out_and_stop(Duration::from_secs(1)).unwrap();
+}
+
+fn test_completion_sender() {
+    let (tx, rx) = mpsc::channel();
+    let audio = AudioBuilder::tone(ToneType::Sine, 550.0, 0.5)
+        .volume(0.1)
+        .completion_sender(tx.clone())
+        .build()
+        .unwrap();
+
+    assert_eq!(rx.recv_timeout(Duration::from_secs(2)).unwrap(), audio.get_id());
+
+    // the waiter thread must not panic after the receiver is dropped
+    drop(rx);
+    AudioBuilder::tone(ToneType::Sine, 550.0, 0.25)
+        .volume(0.1)
+        .completion_sender(tx)
+        .build()
+        .unwrap();
+    thread::sleep(Duration::from_millis(500));
+}
+
+fn test_command_log() {
+    let path = std::env::temp_dir().join("replit_audio_command_log_test");
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+    replit_audio::set_command_log(path).unwrap();
+
+    let mut audio = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+        .volume(0.1)
+        .build()
+        .unwrap();
+    thread::sleep(Duration::from_millis(250));
+    audio.update(&AudioUpdate { volume: 0.2, pan: 0.0, paused: false, does_loop: false, loop_count: 0 }).unwrap();
+
+    replit_audio::clear_command_log();
+    assert_eq!(std::fs::read_to_string(path).unwrap().lines().count(), 3);
+    thread::sleep(Duration::from_secs(1));
+
+    replit_audio::replay_command_log(path, 2.0).unwrap();
+    thread::sleep(Duration::from_secs(1));
+}
+
+fn test_seamless_loop() {
+    let seamless = AudioBuilder::tone(ToneType::Sine, 440.0, 1.0)
+        .volume(0.1)
+        .seamless_loop(Duration::from_millis(200))
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(2500));
+    seamless.stop();
+}
+
+#[test]
+fn test_peek_next_name() {
+    assert!(replit_audio::peek_next_name().starts_with(replit_audio::DEFAULT_NAME_PREFIX));
+}
+
+#[test]
+fn test_build_all_named_repeated_name() {
+    let result = AudioBuilder::tone(ToneType::Sine, 660.0, 1.0)
+        .build_all_named(&["rust_audio_test_c", "rust_audio_test_c"]);
+    assert!(matches!(result, Err(AudioError::DuplicateName { name }) if name == "rust_audio_test_c"));
+}
+
+#[test]
+fn test_file_from_path() {
+    let audio_type = AudioType::file(FileType::Wav, PathBuf::from("sounds/audio.wav")).unwrap();
+    assert_eq!(audio_type, AudioType::File { file: FileType::Wav, path: "sounds/audio.wav".to_string() });
+}
+
+#[test]
+fn test_audio_type_from_path() {
+    let audio_type = AudioType::from_path("sounds/audio.mp3").unwrap();
+    assert_eq!(audio_type, AudioType::File { file: FileType::Mp3, path: "sounds/audio.mp3".to_string() });
+}
+
+#[cfg(unix)]
+#[test]
+fn test_file_from_non_utf8_path() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = Path::new(OsStr::from_bytes(b"audio\xff.wav"));
+    assert!(AudioType::file(FileType::Wav, path).is_err());
+}
+
+#[test]
+fn test_audio_type_same_kind() {
+    let wav = AudioType::File { file: FileType::Wav, path: "a.wav".to_string() };
+    let other_wav = AudioType::File { file: FileType::Wav, path: "b.wav".to_string() };
+    let mp3 = AudioType::File { file: FileType::Mp3, path: "a.wav".to_string() };
+    let square = AudioType::Tone { tone: ToneType::Square, pitch: 440.0, duration: 2.0 };
+    let other_square = AudioType::Tone { tone: ToneType::Square, pitch: 220.0, duration: 1.0 };
+    let sine = AudioType::Tone { tone: ToneType::Sine, pitch: 440.0, duration: 2.0 };
+
+    assert!(wav.same_kind(&other_wav));
+    assert!(!wav.same_kind(&mp3));
+    assert!(square.same_kind(&other_square));
+    assert!(!square.same_kind(&sine));
+    assert!(!wav.same_kind(&square));
+}
+
+#[test]
+fn test_audio_type_approx_eq() {
+    let square = AudioType::Tone { tone: ToneType::Square, pitch: 440.0, duration: 2.0 };
+    let close = AudioType::Tone { tone: ToneType::Square, pitch: 440.0001, duration: 1.9999 };
+    let sine = AudioType::Tone { tone: ToneType::Sine, pitch: 440.0, duration: 2.0 };
+    let wav = AudioType::File { file: FileType::Wav, path: "a.wav".to_string() };
+
+    assert!(square.approx_eq(&close, 0.001));
+    assert!(!square.approx_eq(&close, 0.00001));
+    assert!(!square.approx_eq(&sine, 0.001));
+    // the tolerance is relative, so it scales with both the pitch and the duration
+    let sharp = AudioType::Tone { tone: ToneType::Square, pitch: 444.0, duration: 2.0 };
+    let long = AudioType::Tone { tone: ToneType::Square, pitch: 440.0, duration: 2.1 };
+    assert!(square.approx_eq(&sharp, 0.01));
+    assert!(!square.approx_eq(&long, 0.01));
+    assert!(square.approx_eq(&long, 0.05));
+    assert!(wav.approx_eq(&wav.clone(), 0.001));
+    assert!(!wav.approx_eq(&square, 0.001));
+}
+
+#[test]
+fn test_audio_type_ratio() {
+    let fifth = AudioType::ratio(440.0, 3, 2, ToneType::Sine, 1.0).unwrap();
+    assert_eq!(fifth, AudioType::Tone { tone: ToneType::Sine, pitch: 660.0, duration: 1.0 });
+
+    let third = AudioType::ratio(400.0, 5, 4, ToneType::Saw, 0.5).unwrap();
+    assert_eq!(third, AudioType::Tone { tone: ToneType::Saw, pitch: 500.0, duration: 0.5 });
+
+    assert!(AudioType::ratio(440.0, 3, 0, ToneType::Sine, 1.0).is_err());
+    assert!(AudioType::ratio(440.0, 0, 1, ToneType::Sine, 1.0).is_err());
+    assert!(AudioType::ratio(10_000.0, 3, 1, ToneType::Sine, 1.0).is_err());
+    assert!(AudioType::ratio(f64::NAN, 3, 2, ToneType::Sine, 1.0).is_err());
+}
+
+#[test]
+fn test_replay_invalid_speed() {
+    assert!(replit_audio::replay_command_log("missing_log", 0.0).is_err());
+    assert!(replit_audio::replay_command_log("missing_log", 1.0).is_err());
+}
+
+#[test]
+fn test_audio_update_default() {
+    let update = AudioUpdate::default();
+    assert_eq!(update.volume, 1.0);
+    assert!(!update.paused);
+    assert!(!update.does_loop);
+    assert_eq!(update.loop_count, -1);
+
+    let update = AudioUpdate { volume: 0.5, ..Default::default() };
+    assert_eq!(update.volume, 0.5);
+    assert_eq!(update.loop_count, -1);
 }